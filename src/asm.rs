@@ -0,0 +1,603 @@
+//! Assembles Chip8 programs from the mnemonics in Cowgod's Chip-8 technical
+//! reference, extended with the SUPER-CHIP and XO-CHIP instructions.
+//!
+//! The syntax is the one ``disasm`` produces, so a disassembled listing can
+//! be assembled back into the same ROM. On top of the instructions, sources
+//! can use:
+//!
+//! * labels, written ``name:`` at the start of a line
+//! * constants, written ``name EQU value``
+//! * ``DB`` and ``DW`` to emit lists of bytes and big endian words
+//! * ``INCLUDE "file"`` to assemble another file in place
+//! * ``;`` to start a comment
+//!
+//! Numbers can be decimal, or hexadecimal with a ``#``, ``$`` or ``0x``
+//! prefix, or binary with a ``%`` or ``0b`` prefix. Anywhere a number is
+//! expected, labels, constants and sums of them such as ``table + 2`` are
+//! accepted too. The assembled program starts at address 0x200.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{ Path, PathBuf };
+
+use instruction::Instruction;
+use { MEMORY_SIZE, PROGRAM_START };
+
+/// How deeply ``INCLUDE`` directives may nest, to catch files that include
+/// themselves
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// An error found while assembling, with the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// The file the error is in, or ``None`` for source passed to ``assemble()``
+    pub file: Option<String>,
+
+    /// The line the error is on, counting from 1
+    pub line: usize,
+
+    /// What went wrong
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.file {
+            Some(ref file) => write!(f, "{}:{}: {}", file, self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assemble the given source into a ROM image.
+///
+/// ``INCLUDE`` paths are resolved relative to the working directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    read_lines(source, &Location { file: None, line: 0 }, Path::new("."), 0, &mut lines)?;
+    Assembler::new().assemble(&lines)
+}
+
+/// Assemble the given source file into a ROM image.
+///
+/// ``INCLUDE`` paths are resolved relative to the file that contains them.
+pub fn assemble_file(path: &str) -> Result<Vec<u8>, AsmError> {
+    let mut lines = Vec::new();
+    include_file(Path::new(path), &Location { file: None, line: 0 }, 0, &mut lines)?;
+    Assembler::new().assemble(&lines)
+}
+
+/// Where a line of source came from
+#[derive(Debug, Clone)]
+struct Location {
+    file: Option<String>,
+    line: usize,
+}
+
+impl Location {
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, AsmError> {
+        Err(AsmError { file: self.file.clone(), line: self.line, message: message.into() })
+    }
+}
+
+/// A line of source, with any includes already expanded
+struct SourceLine {
+    location: Location,
+    text: String,
+}
+
+/// Read a source file and append its lines, expanding includes
+fn include_file(path: &Path, from: &Location, depth: usize, lines: &mut Vec<SourceLine>) -> Result<(), AsmError> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return from.error("includes are nested too deeply");
+    }
+
+    let mut source = String::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_string(&mut source)) {
+        return from.error(format!("could not read {}: {}", path.display(), err));
+    }
+
+    let location = Location { file: Some(path.display().to_string()), line: 0 };
+    let directory = path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+    read_lines(&source, &location, &directory, depth, lines)
+}
+
+/// Split source into lines, expanding includes relative to ``directory``
+fn read_lines(source: &str, location: &Location, directory: &Path, depth: usize,
+              lines: &mut Vec<SourceLine>) -> Result<(), AsmError>
+{
+    for (index, text) in source.lines().enumerate() {
+        let location = Location { file: location.file.clone(), line: index + 1 };
+        let code = strip_comment(text).trim();
+
+        let mut words = code.splitn(2, char::is_whitespace);
+        if words.next().is_some_and(|word| word.eq_ignore_ascii_case("include")) {
+            let argument = words.next().unwrap_or("").trim();
+            if argument.len() < 2 || !argument.starts_with('"') || !argument.ends_with('"') {
+                return location.error("INCLUDE expects a quoted file name");
+            }
+            let path = directory.join(&argument[1 .. argument.len() - 1]);
+            include_file(&path, &location, depth + 1, lines)?;
+        } else {
+            lines.push(SourceLine { location, text: code.to_string() });
+        }
+    }
+    Ok(())
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find(';') {
+        Some(index) => &text[.. index],
+        None => text,
+    }
+}
+
+/// A number, or a sum of labels, constants and numbers
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(bool, Term)>,
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+/// An operand of an instruction
+#[derive(Debug, Clone)]
+enum Operand {
+    Register(u8),
+    RegisterRange(u8, u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Pitch,
+    Long(Expr),
+    Value(Expr),
+}
+
+/// A line that produces output
+enum Item {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+enum Symbol {
+    Label(i64),
+    Constant(Expr, Location),
+}
+
+struct Assembler {
+    symbols: HashMap<String, Symbol>,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler { symbols: HashMap::new() }
+    }
+
+    fn assemble(&mut self, lines: &[SourceLine]) -> Result<Vec<u8>, AsmError> {
+        // first pass: find the address of every label
+        let mut items = Vec::new();
+        let mut address = PROGRAM_START as i64;
+
+        for line in lines {
+            let mut text = line.text.as_str();
+
+            if let Some(colon) = text.find(':') {
+                let name = text[.. colon].trim();
+                if is_symbol(name) {
+                    self.define(name, Symbol::Label(address), &line.location)?;
+                    text = text[colon + 1 ..].trim();
+                }
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let mut words = text.splitn(2, char::is_whitespace);
+            let mnemonic = words.next().unwrap_or("").to_uppercase();
+            let rest = words.next().unwrap_or("").trim();
+
+            // name EQU value
+            let mut rest_words = rest.splitn(2, char::is_whitespace);
+            if rest_words.next().is_some_and(|word| word.eq_ignore_ascii_case("equ")) {
+                let name = text.split_whitespace().next().unwrap_or("");
+                if !is_symbol(name) {
+                    return line.location.error(format!("invalid constant name '{}'", name));
+                }
+                let value = parse_expr(rest_words.next().unwrap_or(""), &line.location)?;
+                self.define(name, Symbol::Constant(value, line.location.clone()), &line.location)?;
+                continue;
+            }
+
+            let operands: Vec<&str> = if rest.is_empty() {
+                Vec::new()
+            } else {
+                rest.split(',').map(str::trim).collect()
+            };
+
+            let item = match mnemonic.as_str() {
+                "DB" => Item::Bytes(operands.iter()
+                    .map(|operand| parse_expr(operand, &line.location))
+                    .collect::<Result<_, _>>()?),
+                "DW" => Item::Words(operands.iter()
+                    .map(|operand| parse_expr(operand, &line.location))
+                    .collect::<Result<_, _>>()?),
+                _ => Item::Instruction(mnemonic, operands.iter()
+                    .map(|operand| parse_operand(operand, &line.location))
+                    .collect::<Result<_, _>>()?),
+            };
+
+            let size = match item {
+                Item::Bytes(ref bytes) => bytes.len(),
+                Item::Words(ref words) => 2 * words.len(),
+                Item::Instruction(_, ref operands) => {
+                    if operands.iter().any(|operand| matches!(*operand, Operand::Long(_))) { 4 } else { 2 }
+                },
+            };
+            if operands.is_empty() && size == 0 {
+                return line.location.error(format!("{} needs at least one value", line.text));
+            }
+
+            items.push((item, &line.location));
+            address += size as i64;
+            if address > MEMORY_SIZE as i64 {
+                return line.location.error("program does not fit in memory");
+            }
+        }
+
+        // second pass: encode everything now that all labels are known
+        let mut rom = Vec::new();
+        for (item, location) in items {
+            match item {
+                Item::Bytes(bytes) => {
+                    for byte in bytes {
+                        rom.push(self.byte(&byte, location)?);
+                    }
+                },
+                Item::Words(words) => {
+                    for word in words {
+                        let word = self.ranged(&word, location, -0x8000, 0xffff, "word")?;
+                        rom.push((word >> 8) as u8);
+                        rom.push(word as u8);
+                    }
+                },
+                Item::Instruction(mnemonic, operands) => {
+                    let instruction = self.instruction(&mnemonic, &operands, location)?;
+                    rom.extend(instruction.encode());
+                },
+            }
+        }
+
+        Ok(rom)
+    }
+
+    fn define(&mut self, name: &str, symbol: Symbol, location: &Location) -> Result<(), AsmError> {
+        if self.symbols.contains_key(name) {
+            return location.error(format!("'{}' is defined more than once", name));
+        }
+        self.symbols.insert(name.to_string(), symbol);
+        Ok(())
+    }
+
+    /// Evaluate an expression, following constants at most ``depth`` deep
+    fn evaluate(&self, expr: &Expr, location: &Location, depth: usize) -> Result<i64, AsmError> {
+        if depth > self.symbols.len() {
+            return location.error("constant is defined in terms of itself");
+        }
+
+        let mut total: i64 = 0;
+        for &(negative, ref term) in expr.terms.iter() {
+            let value = match *term {
+                Term::Number(value) => value,
+                Term::Symbol(ref name) => match self.symbols.get(name) {
+                    Some(Symbol::Label(address)) => *address,
+                    Some(Symbol::Constant(expr, location)) => self.evaluate(expr, location, depth + 1)?,
+                    None => return location.error(format!("'{}' is not defined", name)),
+                },
+            };
+            total = if negative { total.wrapping_sub(value) } else { total.wrapping_add(value) };
+        }
+        Ok(total)
+    }
+
+    fn ranged(&self, expr: &Expr, location: &Location, min: i64, max: i64, kind: &str) -> Result<u16, AsmError> {
+        let value = self.evaluate(expr, location, 0)?;
+        if value < min || value > max {
+            return location.error(format!("value {} does not fit in a {}", value, kind));
+        }
+        Ok(value as u16)
+    }
+
+    fn byte(&self, expr: &Expr, location: &Location) -> Result<u8, AsmError> {
+        Ok(self.ranged(expr, location, -0x80, 0xff, "byte")? as u8)
+    }
+
+    fn nibble(&self, expr: &Expr, location: &Location) -> Result<u8, AsmError> {
+        Ok(self.ranged(expr, location, 0, 0xf, "nibble")? as u8)
+    }
+
+    fn address(&self, expr: &Expr, location: &Location) -> Result<u16, AsmError> {
+        self.ranged(expr, location, 0, 0xfff, "12-bit address")
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[Operand], location: &Location)
+        -> Result<Instruction, AsmError>
+    {
+        use self::Operand::*;
+
+        let instruction = match (mnemonic, operands) {
+            ("CLS", &[]) => Instruction::Clear,
+            ("RET", &[]) => Instruction::Return,
+            ("SCR", &[]) => Instruction::ScrollRight,
+            ("SCL", &[]) => Instruction::ScrollLeft,
+            ("EXIT", &[]) => Instruction::Exit,
+            ("LOW", &[]) => Instruction::Lores,
+            ("HIGH", &[]) => Instruction::Hires,
+            ("AUDIO", &[]) => Instruction::Audio,
+            ("SCD", &[Value(ref n)]) => Instruction::ScrollDown(self.nibble(n, location)?),
+            ("SCU", &[Value(ref n)]) => Instruction::ScrollUp(self.nibble(n, location)?),
+            ("PLANE", &[Value(ref n)]) => Instruction::Plane(self.nibble(n, location)?),
+            ("SYS", &[Value(ref a)]) => Instruction::Sys(self.address(a, location)?),
+            ("JP", &[Value(ref a)]) => Instruction::Jump(self.address(a, location)?),
+            ("JP", &[Register(0), Value(ref a)]) => Instruction::JumpOffset(self.address(a, location)?),
+            ("CALL", &[Value(ref a)]) => Instruction::Call(self.address(a, location)?),
+            ("SE", &[Register(x), Register(y)]) => Instruction::SkipEqual(x, y),
+            ("SE", &[Register(x), Value(ref b)]) => Instruction::SkipEqualByte(x, self.byte(b, location)?),
+            ("SNE", &[Register(x), Register(y)]) => Instruction::SkipNotEqual(x, y),
+            ("SNE", &[Register(x), Value(ref b)]) => Instruction::SkipNotEqualByte(x, self.byte(b, location)?),
+            ("ADD", &[Register(x), Register(y)]) => Instruction::Add(x, y),
+            ("ADD", &[Register(x), Value(ref b)]) => Instruction::AddByte(x, self.byte(b, location)?),
+            ("ADD", &[I, Register(x)]) => Instruction::AddI(x),
+            ("OR", &[Register(x), Register(y)]) => Instruction::Or(x, y),
+            ("AND", &[Register(x), Register(y)]) => Instruction::And(x, y),
+            ("XOR", &[Register(x), Register(y)]) => Instruction::Xor(x, y),
+            ("SUB", &[Register(x), Register(y)]) => Instruction::Sub(x, y),
+            ("SUBN", &[Register(x), Register(y)]) => Instruction::SubReverse(x, y),
+            ("SHR", &[Register(x)]) => Instruction::ShiftRight(x, x),
+            ("SHR", &[Register(x), Register(y)]) => Instruction::ShiftRight(x, y),
+            ("SHL", &[Register(x)]) => Instruction::ShiftLeft(x, x),
+            ("SHL", &[Register(x), Register(y)]) => Instruction::ShiftLeft(x, y),
+            ("RND", &[Register(x), Value(ref b)]) => Instruction::Random(x, self.byte(b, location)?),
+            ("DRW", &[Register(x), Register(y), Value(ref n)]) => {
+                Instruction::Draw(x, y, self.nibble(n, location)?)
+            },
+            ("SKP", &[Register(x)]) => Instruction::SkipKey(x),
+            ("SKNP", &[Register(x)]) => Instruction::SkipNotKey(x),
+            ("LD", &[Register(x), Register(y)]) => Instruction::Move(x, y),
+            ("LD", &[Register(x), Value(ref b)]) => Instruction::LoadByte(x, self.byte(b, location)?),
+            ("LD", &[I, Value(ref a)]) => Instruction::LoadI(self.address(a, location)?),
+            ("LD", &[I, Long(ref a)]) => {
+                Instruction::LoadILong(self.ranged(a, location, 0, 0xffff, "16-bit address")?)
+            },
+            ("LD", &[Register(x), DelayTimer]) => Instruction::GetDelay(x),
+            ("LD", &[Register(x), Key]) => Instruction::WaitKey(x),
+            ("LD", &[DelayTimer, Register(x)]) => Instruction::SetDelay(x),
+            ("LD", &[SoundTimer, Register(x)]) => Instruction::SetSound(x),
+            ("LD", &[Font, Register(x)]) => Instruction::Font(x),
+            ("LD", &[BigFont, Register(x)]) => Instruction::BigFont(x),
+            ("LD", &[Bcd, Register(x)]) => Instruction::Bcd(x),
+            ("LD", &[Pitch, Register(x)]) => Instruction::Pitch(x),
+            ("LD", &[IndirectI, Register(x)]) => Instruction::Store(x),
+            ("LD", &[Register(x), IndirectI]) => Instruction::Load(x),
+            ("LD", &[IndirectI, RegisterRange(x, y)]) => Instruction::StoreRange(x, y),
+            ("LD", &[RegisterRange(x, y), IndirectI]) => Instruction::LoadRange(x, y),
+            ("LD", &[Flags, Register(x)]) => Instruction::StoreFlags(x),
+            ("LD", &[Register(x), Flags]) => Instruction::LoadFlags(x),
+            _ => {
+                let known = [
+                    "CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SCD", "SCU",
+                    "PLANE", "SYS", "JP", "CALL", "SE", "SNE", "ADD", "OR", "AND", "XOR", "SUB",
+                    "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "LD",
+                ];
+                return if known.contains(&mnemonic) {
+                    location.error(format!("invalid operands for {}", mnemonic))
+                } else {
+                    location.error(format!("unknown instruction '{}'", mnemonic))
+                };
+            },
+        };
+
+        Ok(instruction)
+    }
+}
+
+/// Returns true if ``name`` can be used as a label or constant
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        },
+        _ => false,
+    }
+}
+
+/// Parse a register name such as ``V3`` or ``vA``
+fn parse_register(text: &str) -> Option<u8> {
+    let text = text.trim();
+    if text.len() == 2 && (text.starts_with('V') || text.starts_with('v')) {
+        u8::from_str_radix(&text[1 ..], 16).ok()
+    } else {
+        None
+    }
+}
+
+fn parse_operand(text: &str, location: &Location) -> Result<Operand, AsmError> {
+    let operand = match text.to_uppercase().as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "HF" => Operand::BigFont,
+        "B" => Operand::Bcd,
+        "R" => Operand::Flags,
+        "PITCH" => Operand::Pitch,
+        _ => {
+            if let Some(register) = parse_register(text) {
+                return Ok(Operand::Register(register));
+            }
+
+            let mut range = text.splitn(2, '-');
+            if let (Some(Some(x)), Some(Some(y))) = (range.next().map(parse_register), range.next().map(parse_register)) {
+                return Ok(Operand::RegisterRange(x, y));
+            }
+
+            let mut words = text.splitn(2, char::is_whitespace);
+            if words.next().is_some_and(|word| word.eq_ignore_ascii_case("long")) {
+                return Ok(Operand::Long(parse_expr(words.next().unwrap_or(""), location)?));
+            }
+
+            Operand::Value(parse_expr(text, location)?)
+        },
+    };
+
+    Ok(operand)
+}
+
+fn parse_expr(text: &str, location: &Location) -> Result<Expr, AsmError> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut rest = text.trim();
+
+    if rest.starts_with('-') {
+        negative = true;
+        rest = rest[1 ..].trim_start();
+    } else if rest.starts_with('+') {
+        rest = rest[1 ..].trim_start();
+    }
+
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let term = rest[.. end].trim();
+        if term.is_empty() {
+            return location.error(format!("expected a value in '{}'", text.trim()));
+        }
+        terms.push((negative, parse_term(term, location)?));
+
+        if end == rest.len() {
+            break;
+        }
+        negative = rest[end ..].starts_with('-');
+        rest = rest[end + 1 ..].trim_start();
+    }
+
+    Ok(Expr { terms })
+}
+
+fn parse_term(text: &str, location: &Location) -> Result<Term, AsmError> {
+    let lower = text.to_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix('#').or_else(|| lower.strip_prefix('$')).or_else(|| lower.strip_prefix("0x")) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix('%').or_else(|| lower.strip_prefix("0b")) {
+        i64::from_str_radix(binary, 2)
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse()
+    } else if is_symbol(text) {
+        return Ok(Term::Symbol(text.to_string()));
+    } else {
+        return location.error(format!("'{}' is not a number or name", text));
+    };
+
+    number.map(Term::Number).or_else(|_| location.error(format!("'{}' is not a valid number", text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use disasm::{ disassemble_rom, Syntax };
+
+    #[test]
+    fn round_trips_through_the_disassembler() {
+        let source = "\
+            start:
+                CLS
+                LD V0, #05
+                LD V1, 10
+                LD I, sprite
+                DRW V0, V1, 5
+                CALL wait
+                ADD V0, 1
+                SE V0, 60
+                JP start
+            loop:
+                JP loop
+            wait:
+                LD V2, 30
+                LD DT, V2
+            wait_loop:
+                LD V2, DT
+                SE V2, 0
+                JP wait_loop
+                RET
+            sprite:
+                DB #F0, #90, #F0, #90, #90
+        ";
+
+        let rom = assemble(source).unwrap();
+        let listing = disassemble_rom(&rom, Syntax::Cowgod);
+        assert_eq!(assemble(&listing).unwrap(), rom, "listing:\n{}", listing);
+    }
+
+    #[test]
+    fn resolves_labels_and_constants() {
+        let source = "\
+            SPEED EQU 3
+            TWICE EQU SPEED + SPEED
+                LD V0, TWICE
+                JP end
+            end:
+                JP end + 0
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![0x60, 0x06, 0x12, 0x04, 0x12, 0x04]);
+    }
+
+    #[test]
+    fn emits_bytes_and_words() {
+        let source = "\
+                DB 1, #ff, %101
+                DW #1234, table
+            table:
+        ";
+        assert_eq!(assemble(source).unwrap(), vec![0x01, 0xff, 0x05, 0x12, 0x34, 0x02, 0x07]);
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let err = assemble("CLS\n\nLD V0, missing\n").unwrap_err();
+        assert_eq!(err.file, None);
+        assert_eq!(err.line, 3);
+    }
+
+    #[test]
+    fn limits_how_deeply_includes_nest() {
+        let path = std::env::temp_dir().join(format!("chip8-asm-include-{}.asm", std::process::id()));
+        let name = path.file_name().unwrap().to_str().unwrap().to_string();
+        fs::write(&path, format!("INCLUDE \"{}\"\n", name)).unwrap();
+
+        let result = assemble_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+
+        let err = result.unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.message.contains("nested too deeply"), "{}", err);
+    }
+}
@@ -0,0 +1,259 @@
+//! Synthesizes the sound of the buzzer from the state of a Chip8.
+//!
+//! XO-CHIP programs load a 1-bit audio pattern and choose the rate it is
+//! played at through the pitch register. Programs that never load a pattern
+//! get a ``Tone`` instead, since their pattern buffer is silent.
+//!
+//! ``Synth`` is fed once per frame and produces the samples for that frame,
+//! so a frontend can queue them up without needing an audio callback that
+//! shares the Chip8 with the emulation thread.
+
+use std::f32::consts::PI;
+
+use { Chip8, Rng };
+
+/// The number of bits in the XO-CHIP audio pattern
+const PATTERN_BITS: f32 = 128.0;
+
+/// How long the volume takes to fade fully in or out, in seconds. Starting
+/// and stopping the sound abruptly makes an audible click.
+const RAMP_TIME: f32 = 0.005;
+
+/// The shape of the tone played for programs without an audio pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    /// High for half of each cycle and low for the other half
+    Square,
+
+    /// High for ``duty`` of each cycle, from 0 to 1
+    Pulse { duty: f32 },
+
+    Triangle,
+
+    Sine,
+
+    /// A random level, changing ``frequency`` times a second
+    Noise,
+}
+
+impl Waveform {
+    /// Parse a waveform: ``square``, ``pulse``, ``pulse:<duty>``,
+    /// ``triangle``, ``sine`` or ``noise``. The duty is the fraction of each
+    /// cycle that the pulse is high, 0.25 unless given.
+    pub fn parse(spec: &str) -> Result<Waveform, String> {
+        let mut parts = spec.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let argument = parts.next();
+
+        match (name, argument) {
+            ("square", None) => Ok(Waveform::Square),
+            ("pulse", None) => Ok(Waveform::Pulse { duty: 0.25 }),
+            ("pulse", Some(duty)) => match duty.parse::<f32>() {
+                Ok(duty) if duty > 0.0 && duty < 1.0 => Ok(Waveform::Pulse { duty }),
+                _ => Err(format!("'{}' is not a duty between 0 and 1", duty)),
+            },
+            ("triangle", None) => Ok(Waveform::Triangle),
+            ("sine", None) => Ok(Waveform::Sine),
+            ("noise", None) => Ok(Waveform::Noise),
+            _ => Err(format!("unknown waveform '{}', expected square, pulse[:<duty>], triangle, sine or noise", spec)),
+        }
+    }
+}
+
+/// The tone played for programs without an audio pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+
+    /// The frequency in Hz
+    pub frequency: f32,
+}
+
+impl Default for Tone {
+    /// A 440Hz square wave, like the original frontend
+    fn default() -> Tone {
+        Tone { waveform: Waveform::Square, frequency: 440.0 }
+    }
+}
+
+/// Generates the samples of the buzzer, one frame at a time
+#[derive(Debug, Clone)]
+pub struct Synth {
+    sample_rate: f32,
+
+    /// The volume the sound fades in to, from 0 to 1
+    volume: f32,
+
+    /// The current volume, which moves towards ``volume`` while the sound
+    /// is playing, and towards 0 while it isn't
+    gain: f32,
+
+    tone: Tone,
+
+    /// The cutoff frequency of the low-pass filter, if there is one
+    low_pass: Option<f32>,
+
+    /// The output of the low-pass filter for the last sample
+    filtered: f32,
+
+    /// The source of the noise waveform, and its current level
+    rng: Rng,
+    noise: f32,
+
+    /// How far through the audio pattern playback is, in bits, or through
+    /// a cycle of the tone, from 0 to 1
+    position: f32,
+
+    /// The fraction of a sample left over from the frames generated so far
+    remainder: f32,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, volume: f32) -> Synth {
+        Synth {
+            sample_rate: sample_rate as f32,
+            volume,
+            gain: 0.0,
+            tone: Tone::default(),
+            low_pass: None,
+            filtered: 0.0,
+            rng: Rng::new(0),
+            noise: 1.0,
+            position: 0.0,
+            remainder: 0.0,
+        }
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Change the volume, from 0 to 1. The sound fades to the new volume
+    /// rather than jumping.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn tone(&self) -> Tone {
+        self.tone
+    }
+
+    pub fn set_tone(&mut self, tone: Tone) {
+        self.tone = tone;
+    }
+
+    pub fn low_pass(&self) -> Option<f32> {
+        self.low_pass
+    }
+
+    /// Smooth the sound with a low-pass filter that cuts off above
+    /// ``cutoff`` Hz, or turn the filter off with None. A cutoff of a few
+    /// kHz softens the edges of the waves like a small speaker does.
+    pub fn set_low_pass(&mut self, cutoff: Option<f32>) {
+        self.low_pass = cutoff;
+    }
+
+    /// Get the number of samples in the next 60Hz frame. Frames don't hold
+    /// a whole number of samples at most sample rates, so this varies by
+    /// one from frame to frame.
+    pub fn frame_samples(&mut self) -> usize {
+        let samples = self.sample_rate / 60.0 + self.remainder;
+        self.remainder = samples.fract();
+        samples as usize
+    }
+
+    /// Fill ``out`` with the next samples, from -1 to 1, playing the sound
+    /// of ``chip`` if ``playing`` is set.
+    pub fn generate(&mut self, chip: &Chip8, playing: bool, out: &mut [f32]) {
+        let pattern = chip.get_audio_pattern();
+        let has_pattern = pattern.iter().any(|&byte| byte != 0);
+        let step = if has_pattern {
+            chip.get_pattern_rate() / self.sample_rate
+        } else {
+            self.tone.frequency / self.sample_rate
+        };
+        let period = if has_pattern { PATTERN_BITS } else { 1.0 };
+
+        // the position may be far into the pattern if the program has just
+        // cleared it, which would be way outside a cycle of the tone
+        self.position %= period;
+
+        let target = if playing { self.volume } else { 0.0 };
+        let ramp = 1.0 / (RAMP_TIME * self.sample_rate);
+
+        // the smoothing factor of a one pole low-pass filter
+        let smoothing = match self.low_pass {
+            Some(cutoff) => 1.0 - (-2.0 * PI * cutoff / self.sample_rate).exp(),
+            None => 1.0,
+        };
+
+        for sample in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + ramp).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp).max(target);
+            }
+
+            // the wave keeps running while silent, so it picks up smoothly
+            // if the sound starts again while fading out
+            let level = if has_pattern {
+                let bit = self.position as usize;
+                if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+            } else {
+                self.tone_level()
+            };
+            self.filtered += smoothing * (level * self.gain - self.filtered);
+            *sample = self.filtered;
+
+            self.position += step;
+            if self.position >= period {
+                self.position %= period;
+
+                // noise picks a new level once per cycle
+                if !has_pattern && self.tone.waveform == Waveform::Noise {
+                    self.noise = self.rng.next_u8() as f32 / 127.5 - 1.0;
+                }
+            }
+        }
+    }
+
+    /// Get the level of the tone at the current position in its cycle,
+    /// from -1 to 1
+    fn tone_level(&self) -> f32 {
+        let phase = self.position;
+        match self.tone.waveform {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Pulse { duty } => if phase < duty { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Noise => self.noise,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Quirks;
+
+    #[test]
+    fn switching_from_a_pattern_to_the_tone_stays_in_range() {
+        // ld i, pattern; f002; ld i, silence; f002; jp loop
+        let mut rom = vec![0xa2, 0x0a, 0xf0, 0x02, 0xa2, 0x1a, 0xf0, 0x02, 0x12, 0x08];
+        rom.extend([0x55; 16]);
+        rom.extend([0x00; 16]);
+        let mut chip = Chip8::with_rom_data(&rom, Quirks::default()).unwrap();
+
+        let mut synth = Synth::new(44100, 1.0);
+        synth.set_tone(Tone { waveform: Waveform::Triangle, frequency: 440.0 });
+        let mut samples = vec![0.0; 1000];
+
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        synth.generate(&chip, true, &mut samples);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        synth.generate(&chip, true, &mut samples);
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+    }
+}
@@ -0,0 +1,35 @@
+extern crate chip8;
+
+use chip8::asm::assemble_file;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let (source_file, rom_file) = match args.len() {
+        2 => (&args[1], Path::new(&args[1]).with_extension("ch8").display().to_string()),
+        4 if args[2] == "-o" => (&args[1], args[3].clone()),
+        _ => {
+            println!("Usage: {} <path to source file> [-o <path to rom file>]", args[0]);
+            std::process::exit(1);
+        },
+    };
+
+    let rom = match assemble_file(source_file) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        },
+    };
+
+    if let Err(err) = File::create(&rom_file).and_then(|mut file| file.write_all(&rom)) {
+        eprintln!("Could not write {}: {}", rom_file, err);
+        std::process::exit(1);
+    }
+
+    println!("Assembled {} bytes into {}", rom.len(), rom_file);
+}
@@ -0,0 +1,40 @@
+extern crate chip8;
+
+use chip8::disasm::{ disassemble_rom, Syntax };
+
+use std::fs::File;
+use std::io::Read;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut syntax = Syntax::Cowgod;
+    let mut rom_file = None;
+    for arg in &args[1 ..] {
+        match arg.as_str() {
+            "--cowgod" => syntax = Syntax::Cowgod,
+            "--octo" => syntax = Syntax::Octo,
+            _ if rom_file.is_none() && !arg.starts_with("--") => rom_file = Some(arg),
+            _ => {
+                rom_file = None;
+                break;
+            },
+        }
+    }
+
+    let rom_file = match rom_file {
+        Some(rom_file) => rom_file,
+        None => {
+            println!("Usage: {} [--cowgod | --octo] <path to rom file>", args[0]);
+            std::process::exit(1);
+        },
+    };
+
+    let mut rom = Vec::new();
+    if let Err(err) = File::open(rom_file).and_then(|mut file| file.read_to_end(&mut rom)) {
+        println!("Could not read {}: {}", rom_file, err);
+        std::process::exit(1);
+    }
+
+    print!("{}", disassemble_rom(&rom, syntax));
+}
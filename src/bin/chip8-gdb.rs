@@ -0,0 +1,55 @@
+extern crate chip8;
+
+use chip8::{ Chip8, Quirks };
+use chip8::gdb::GdbServer;
+
+use std::net::TcpListener;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let (rom_file, port) = match args.len() {
+        2 => (&args[1], Some(1234)),
+        4 if args[1] == "--port" => (&args[3], args[2].parse::<u16>().ok()),
+        _ => (&args[0], None),
+    };
+    let port = match port {
+        Some(port) => port,
+        None => {
+            println!("Usage: {} [--port <port>] <path to rom file>", args[0]);
+            std::process::exit(1);
+        },
+    };
+
+    let chip = match Chip8::from_rom_file(rom_file, Quirks::default()) {
+        Ok(chip) => chip,
+        Err(err) => {
+            eprintln!("Could not load {}: {}", rom_file, err);
+            std::process::exit(1);
+        },
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Could not listen on port {}: {}", port, err);
+            std::process::exit(1);
+        },
+    };
+    println!("Waiting for a debugger on 127.0.0.1:{}", port);
+
+    let (stream, address) = match listener.accept() {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Could not accept a connection: {}", err);
+            std::process::exit(1);
+        },
+    };
+    println!("Debugger connected from {}", address);
+
+    let mut server = GdbServer::new(chip, 15);
+    if let Err(err) = server.serve(stream) {
+        eprintln!("Debugging session ended: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,147 @@
+extern crate chip8;
+
+use chip8::{ Chip8, Quirks };
+use chip8::headless::{ load_script, Headless };
+
+use std::fs::File;
+use std::io::Write;
+
+const USAGE: &str = "\
+Usage: {} [options] <path to rom file>
+
+Options:
+  --frames <n>             run n 60Hz frames (default 60)
+  --instructions <n>       run n instructions instead of whole frames
+  --cycles-per-frame <n>   instructions executed per frame (default 15)
+  --keys <script>          apply the key presses in a script, one
+                           '<frame> <key> down|up' per line
+  --mode <name>            the platform the ROM was written for, which
+                           picks its quirks: chip8 (or vip), schip,
+                           xochip or modern (the default)
+  --quirks <list>          quirks to change, separated by commas, as for
+                           the SDL frontend
+  --seed <n>               seed the random number generator (default 0)
+  --png <file>             save the final display as a PNG image
+  --scale <n>              size of a display pixel in the PNG (default 1)
+  --ascii                  print the final display as text, the default
+                           when no PNG is saved";
+
+/// The options given on the command line
+struct Options {
+    rom_file: String,
+    frames: u64,
+    instructions: Option<u64>,
+    cycles_per_frame: usize,
+    quirks: Quirks,
+    quirk_specs: Vec<String>,
+    keys: Option<String>,
+    seed: u64,
+    png: Option<String>,
+    scale: usize,
+    ascii: bool,
+}
+
+fn parse_args(args: &[String]) -> Option<Options> {
+    let mut options = Options {
+        rom_file: String::new(),
+        frames: 60,
+        instructions: None,
+        cycles_per_frame: 15,
+        quirks: Quirks::default(),
+        quirk_specs: Vec::new(),
+        keys: None,
+        seed: 0,
+        png: None,
+        scale: 1,
+        ascii: false,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => options.frames = args.next()?.parse().ok()?,
+            "--instructions" => options.instructions = Some(args.next()?.parse().ok()?),
+            "--cycles-per-frame" => options.cycles_per_frame = args.next()?.parse().ok()?,
+            "--mode" => options.quirks = Quirks::preset(args.next()?)?,
+            "--quirks" => options.quirk_specs.push(args.next()?.clone()),
+            "--keys" => options.keys = Some(args.next()?.clone()),
+            "--seed" => options.seed = args.next()?.parse().ok()?,
+            "--png" => options.png = Some(args.next()?.clone()),
+            "--scale" => options.scale = args.next()?.parse().ok()?,
+            "--ascii" => options.ascii = true,
+            _ if options.rom_file.is_empty() && !arg.starts_with("--") => options.rom_file = arg.clone(),
+            _ => return None,
+        }
+    }
+
+    if options.rom_file.is_empty() || options.cycles_per_frame == 0 {
+        return None;
+    }
+    Some(options)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let options = match parse_args(&args[1 ..]) {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE.replace("{}", &args[0]));
+            std::process::exit(1);
+        },
+    };
+
+    // quirks are applied on top of the mode, in whatever order they came
+    let mut quirks = options.quirks;
+    for spec in &options.quirk_specs {
+        if let Err(err) = quirks.apply(spec) {
+            eprintln!("Invalid --quirks: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let mut chip = match Chip8::from_rom_file(&options.rom_file, quirks) {
+        Ok(chip) => chip,
+        Err(err) => {
+            eprintln!("Could not load {}: {}", options.rom_file, err);
+            std::process::exit(1);
+        },
+    };
+    chip.seed_rng(options.seed);
+
+    let mut runner = Headless::new(chip, options.cycles_per_frame);
+    if let Some(ref keys) = options.keys {
+        match load_script(keys) {
+            Ok(events) => runner.add_key_events(&events),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            },
+        }
+    }
+
+    let result = match options.instructions {
+        Some(instructions) => runner.run_instructions(instructions),
+        None => runner.run_frames(options.frames),
+    };
+
+    if options.ascii || options.png.is_none() {
+        print!("{}", runner.framebuffer_ascii());
+        println!();
+    }
+    println!("Frames: {}", runner.frame());
+    print!("{}", runner.chip());
+
+    if let Some(ref png) = options.png {
+        let data = runner.framebuffer_png(options.scale);
+        if let Err(err) = File::create(png).and_then(|mut file| file.write_all(&data)) {
+            eprintln!("Could not write {}: {}", png, err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(err) = result {
+        eprintln!("[ERROR] {}", err);
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,141 @@
+extern crate chip8;
+
+use std::io::{ self, Read };
+use std::sync::mpsc::{ self, Receiver };
+use std::thread;
+
+use chip8::Chip8;
+use chip8::frontend::Input;
+
+/// Reads the keypad from the terminal.
+///
+/// Terminals only report key presses, not releases, so a key counts as
+/// held for a few frames after it was last pressed. Holding a key down makes
+/// the terminal repeat it, which keeps it held.
+pub struct Keys {
+    /// Chunks of bytes read from stdin by a background thread
+    input: Receiver<Vec<u8>>,
+
+    /// The number of frames a key stays held after it is pressed
+    hold_frames: u64,
+
+    /// The frame each key of the keypad is held until
+    held_until: [u64; 0x10],
+
+    /// The number of frames polled so far
+    frame: u64,
+
+    /// Set when Tab is pressed, until it is taken
+    toggle_panel: bool,
+
+    /// The start of an escape sequence that hadn't been read in full by the
+    /// last frame
+    pending: Vec<u8>,
+}
+
+impl Keys {
+    pub fn new(hold_frames: u64) -> Keys {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buffer = [0; 64];
+            while let Ok(read) = stdin.read(&mut buffer) {
+                if read == 0 || sender.send(buffer[.. read].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Keys {
+            input: receiver,
+            hold_frames,
+            held_until: [0; 0x10],
+            frame: 0,
+            toggle_panel: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns true if Tab was pressed since the last call
+    pub fn take_toggle_panel(&mut self) -> bool {
+        std::mem::replace(&mut self.toggle_panel, false)
+    }
+}
+
+impl Input for Keys {
+    fn poll(&mut self, chip: &mut Chip8) -> bool {
+        self.frame += 1;
+
+        let mut received = false;
+        while let Ok(chunk) = self.input.try_recv() {
+            self.pending.extend(chunk);
+            received = true;
+        }
+
+        // an escape sequence split across reads is finished within a frame,
+        // so an escape with nothing after it by now was the Esc key
+        if !received && !self.pending.is_empty() {
+            if self.pending == [0x1b] {
+                return false;
+            }
+            self.pending.clear();
+        }
+
+        let bytes = std::mem::take(&mut self.pending);
+        let mut index = 0;
+        while index < bytes.len() {
+            let byte = bytes[index];
+            index += 1;
+            match byte {
+                // ctrl + c
+                0x03 => return false,
+                b'\t' => self.toggle_panel = !self.toggle_panel,
+                0x1b => match escape_length(&bytes[index ..]) {
+                    Some(length) => index += length,
+                    None => {
+                        self.pending = bytes[index - 1 ..].to_vec();
+                        break;
+                    },
+                },
+                _ => if let Some(key) = keypad_key(byte) {
+                    self.held_until[key] = self.frame + self.hold_frames;
+                },
+            }
+        }
+
+        for key in 0 .. 0x10 {
+            chip.set_input(key, self.held_until[key] > self.frame);
+        }
+        true
+    }
+}
+
+/// Get the number of bytes after an escape that belong to its sequence, or
+/// None if the sequence hasn't been read in full yet
+fn escape_length(bytes: &[u8]) -> Option<usize> {
+    match bytes.first() {
+        // escape sequences such as the arrow keys are skipped: a CSI runs up
+        // to a final byte from @ to ~, and an SS3 has a single byte after it
+        Some(b'[') => bytes[1 ..].iter()
+            .position(|byte| (0x40 ..= 0x7e).contains(byte))
+            .map(|end| end + 2),
+        Some(b'O') if bytes.len() < 2 => None,
+        Some(b'O') => Some(2),
+
+        // alt with a key
+        Some(_) => Some(1),
+        None => None,
+    }
+}
+
+/// Get the keypad key a character is bound to, using the same layout as
+/// the SDL frontend
+fn keypad_key(byte: u8) -> Option<usize> {
+    match byte.to_ascii_lowercase() {
+        b'1' => Some(0x1), b'2' => Some(0x2), b'3' => Some(0x3), b'4' => Some(0xC),
+        b'q' => Some(0x4), b'w' => Some(0x5), b'e' => Some(0x6), b'r' => Some(0xD),
+        b'a' => Some(0x7), b's' => Some(0x8), b'd' => Some(0x9), b'f' => Some(0xE),
+        b'z' => Some(0xA), b'x' => Some(0x0), b'c' => Some(0xB), b'v' => Some(0xF),
+        _ => None,
+    }
+}
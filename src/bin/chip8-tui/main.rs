@@ -0,0 +1,151 @@
+extern crate chip8;
+
+use chip8::{ Chip8, Quirks };
+use chip8::debugger::StopReason;
+use chip8::frontend::{ Audio, Machine };
+
+mod terminal;
+use terminal::{ RawMode, Terminal };
+
+mod keys;
+use keys::Keys;
+
+use std::io::{ self, Write };
+
+/// Rings the terminal bell when the buzzer starts, or stays silent
+struct Bell {
+    enabled: bool,
+    playing: bool,
+}
+
+impl Audio for Bell {
+    fn set_playing(&mut self, playing: bool, _chip: &Chip8) {
+        if self.enabled && playing && !self.playing {
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+        self.playing = playing;
+    }
+}
+
+const USAGE: &str = "\
+Usage: {} [options] <path to rom file>
+
+Options:
+  --panel                   show the registers and disassembly next to the
+                            display
+  --mute                    don't ring the terminal bell for the buzzer
+  --hold <frames>           how long a key stays held after it is pressed
+                            (default 30)
+  --mode <name>             the platform the ROM was written for, which
+                            picks its quirks: chip8 (or vip), schip,
+                            xochip or modern (the default)
+  --quirks <list>           quirks to change, separated by commas, as for
+                            the SDL frontend
+  --cycles-per-frame <n>    instructions executed per 60Hz frame
+                            (default 15)
+
+Keys:
+  1 2 3 4 / q w e r / a s d f / z x c v   the keypad
+  Tab                                     toggle the side panel
+  Esc or Ctrl + C                         quit";
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    let mut panel = false;
+    let mut mute = false;
+    // terminals wait about half a second before repeating a held key, and
+    // the key has to stay held until then
+    let mut hold_frames = 30;
+    let mut quirks = Quirks::default();
+    let mut quirk_specs = Vec::new();
+    let mut cycles_per_frame = 15;
+    let mut rom_file = None;
+    let mut usage = false;
+    let mut arg_iter = args[1 ..].iter();
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--panel" => panel = true,
+            "--mute" => mute = true,
+            "--hold" => match arg_iter.next().and_then(|frames| frames.parse().ok()) {
+                Some(frames) => hold_frames = frames,
+                None => usage = true,
+            },
+            "--mode" => match arg_iter.next().and_then(|mode| Quirks::preset(mode)) {
+                Some(preset) => quirks = preset,
+                None => usage = true,
+            },
+            "--quirks" => match arg_iter.next() {
+                Some(spec) => quirk_specs.push(spec),
+                None => usage = true,
+            },
+            "--cycles-per-frame" => match arg_iter.next().and_then(|cycles| cycles.parse().ok()) {
+                Some(cycles) if cycles > 0 => cycles_per_frame = cycles,
+                _ => usage = true,
+            },
+            _ if rom_file.is_none() && !arg.starts_with("--") => rom_file = Some(arg),
+            _ => usage = true,
+        }
+    }
+
+    let rom_file = match rom_file {
+        Some(rom_file) if !usage => rom_file,
+        _ => {
+            println!("{}", USAGE.replace("{}", &args[0]));
+            std::process::exit(1);
+        },
+    };
+
+    // quirks are applied on top of the mode, in whatever order they came
+    for spec in quirk_specs {
+        if let Err(err) = quirks.apply(spec) {
+            eprintln!("Invalid --quirks: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    let chip = match Chip8::from_rom_file(rom_file, quirks) {
+        Ok(chip) => chip,
+        Err(err) => {
+            eprintln!("Could not load {}: {}", rom_file, err);
+            std::process::exit(1);
+        },
+    };
+
+    let raw_mode = match RawMode::enable() {
+        Ok(raw_mode) => raw_mode,
+        Err(err) => {
+            eprintln!("Could not set up the terminal: {}", err);
+            std::process::exit(1);
+        },
+    };
+
+    let bell = Bell { enabled: !mute, playing: false };
+    let mut machine = Machine::new(chip, Terminal::new(panel), bell, Keys::new(hold_frames), cycles_per_frame);
+    machine.draw();
+
+    let mut was_waiting = false;
+    while machine.poll_input() && !machine.chip().has_exited() {
+        if machine.input_mut().take_toggle_panel() {
+            let terminal = machine.display_mut();
+            terminal.panel = !terminal.panel;
+            machine.draw();
+        }
+
+        machine.wait_for_frame();
+        if let Err(StopReason::Error(err)) = machine.run_frame() {
+            machine.display_mut().status = format!("[WARNING] {}", err);
+        }
+
+        // the panel shows the registers, which change every frame, and
+        // the status line shows when the program is waiting for a key
+        let waiting = machine.chip().is_waiting_for_key();
+        if machine.display().panel || waiting != was_waiting {
+            machine.draw();
+        }
+        was_waiting = waiting;
+    }
+
+    drop(raw_mode);
+}
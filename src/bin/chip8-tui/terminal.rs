@@ -0,0 +1,129 @@
+extern crate chip8;
+
+use std::io::{ self, Write };
+use std::process::{ Command, Stdio };
+
+use chip8::Chip8;
+use chip8::disasm::{ self, Syntax };
+use chip8::frontend::Display;
+
+/// Puts the terminal in raw mode, so key presses arrive straight away and
+/// aren't echoed, and restores it when dropped.
+pub struct RawMode {
+    /// The settings to restore, as printed by ``stty -g``
+    saved: String,
+}
+
+impl RawMode {
+    pub fn enable() -> io::Result<RawMode> {
+        let output = Command::new("stty").arg("-g").stdin(Stdio::inherit()).output()?;
+        if !output.status.success() {
+            return Err(io::Error::new(io::ErrorKind::Other, "stdin is not a terminal"));
+        }
+        let saved = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        Command::new("stty").args(["raw", "-echo"]).stdin(Stdio::inherit()).status()?;
+
+        // switch to the alternate screen and hide the cursor
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+
+        Ok(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = Command::new("stty").arg(&self.saved).stdin(Stdio::inherit()).status();
+    }
+}
+
+/// Draws the display in the terminal with Unicode half blocks, so every
+/// line of text holds two rows of pixels.
+pub struct Terminal {
+    /// Whether to show the registers and disassembly next to the display
+    pub panel: bool,
+
+    /// A message shown below the display, such as the last emulation error
+    pub status: String,
+}
+
+impl Terminal {
+    pub fn new(panel: bool) -> Terminal {
+        Terminal {
+            panel,
+            status: String::new(),
+        }
+    }
+
+    /// The lines of the side panel: the registers, then the instructions
+    /// around the program counter
+    fn panel_lines(&self, chip: &Chip8) -> Vec<String> {
+        let mut lines = Vec::new();
+        for row in 0 .. 4 {
+            let registers: Vec<String> = (row * 4 .. row * 4 + 4)
+                .map(|register| format!("V{:X}={:02X}", register, chip.get_v(register)))
+                .collect();
+            lines.push(registers.join(" "));
+        }
+        lines.push(format!("I={:04X} PC={:04X}", chip.get_i(), chip.get_pc()));
+        lines.push(format!("SP={:X} DT={:02X} ST={:02X}", chip.get_sp(), chip.get_dt(), chip.get_st()));
+        lines.push(String::new());
+
+        // instructions are usually 2-byte aligned, so start a few before the
+        // program counter on the same alignment
+        let mem = chip.get_mem();
+        let pc = chip.get_pc() as usize;
+        let mut address = pc.saturating_sub(6);
+        for _ in 0 .. 8 {
+            if address >= mem.len() {
+                break;
+            }
+            let (text, size) = disasm::disassemble_at(&mem[address ..], Syntax::Cowgod);
+            let marker = if address == pc { '>' } else { ' ' };
+            lines.push(format!("{} {:04X} {}", marker, address, text));
+            address += size;
+        }
+        lines
+    }
+}
+
+impl Display for Terminal {
+    fn draw(&mut self, chip: &Chip8) {
+        let (width, height) = (chip.display_width(), chip.display_height());
+        let panel = if self.panel { self.panel_lines(chip) } else { Vec::new() };
+
+        // move to the top left corner and redraw everything over the top
+        let mut frame = String::from("\x1b[H");
+        for row in 0 .. (height + 1) / 2 {
+            for x in 0 .. width {
+                let top = chip.get_pixel(x, row * 2);
+                let bottom = row * 2 + 1 < height && chip.get_pixel(x, row * 2 + 1);
+                frame.push(match (top, bottom) {
+                    (true, true) => '\u{2588}',
+                    (true, false) => '\u{2580}',
+                    (false, true) => '\u{2584}',
+                    (false, false) => ' ',
+                });
+            }
+            if let Some(line) = panel.get(row) {
+                frame.push_str("  ");
+                frame.push_str(line);
+            }
+            // raw mode needs explicit carriage returns, and the rest of the
+            // line is cleared in case the previous frame was wider
+            frame.push_str("\x1b[K\r\n");
+        }
+        frame.push_str(&self.status);
+        if chip.is_waiting_for_key() {
+            frame.push_str(if self.status.is_empty() { "Waiting for a key" } else { " (waiting for a key)" });
+        }
+        frame.push_str("\x1b[K\x1b[J");
+
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(frame.as_bytes());
+        let _ = stdout.flush();
+    }
+}
@@ -1,57 +1,87 @@
 extern crate sdl2;
+extern crate chip8;
 
 use sdl2::AudioSubsystem;
-use sdl2::audio::{AudioCallback, AudioSpecDesired, AudioDevice};
+use sdl2::audio::{ AudioQueue, AudioSpecDesired };
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32
-}
+use chip8::Chip8;
+use chip8::audio::Synth;
+use chip8::frontend::Audio;
 
-impl AudioCallback for SquareWave {
-    type Channel = f32;
-
-    fn callback(&mut self, out: &mut [f32]) {
-        for x in out.iter_mut() {
-            *x = if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            };
-            self.phase = (self.phase + self.phase_inc) % 1.0;
-        }
-    }
-}
+/// The number of frames of sound kept queued ahead of the device. More
+/// delays the sound, and fewer risks gaps when a frame runs late.
+const QUEUED_FRAMES: usize = 3;
 
+/// Plays the sound of the Chip8, queueing a frame of samples at a time
 pub struct Buzzer {
-    device: AudioDevice<SquareWave>,
+    queue: AudioQueue<f32>,
+    synth: Synth,
+
+    /// The samples of the frame being queued
+    samples: Vec<f32>,
+
+    /// When muted the buzzer stays silent, whatever the sound timer says
+    muted: bool,
 }
 
 impl Buzzer {
-    pub fn new(audio_subsystem: &AudioSubsystem) -> Buzzer {
+    /// Open the audio device, playing the sound at ``volume``, from 0 to 1
+    pub fn new(audio_subsystem: &AudioSubsystem, volume: f32) -> Buzzer {
         let spec = AudioSpecDesired {
-            freq: Some(3000),
+            freq: Some(44100),
             channels: Some(1),
-            samples: None
+            samples: Some(512),
         };
-        
-        let device = audio_subsystem.open_playback(None, &spec, |spec| {
-          SquareWave {
-              phase_inc: 440.0 / spec.freq as f32,
-              phase: 0.0,
-              volume: 0.25
-          }
-        }).unwrap();
-        
-        Buzzer { device }
+
+        let queue = audio_subsystem.open_queue::<f32, _>(None, &spec).unwrap();
+        let synth = Synth::new(queue.spec().freq as u32, volume);
+        queue.resume();
+
+        Buzzer { queue, synth, samples: Vec::new(), muted: false }
     }
 
-    pub fn set(&self, state: bool) {
-        if state {
-            self.device.resume();
-        } else {
-            self.device.pause();
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Silence the buzzer, or let it play again. It fades out and in like
+    /// the sound of the program does.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    /// Get the synthesizer, to change the volume or tone
+    pub fn synth_mut(&mut self) -> &mut Synth {
+        &mut self.synth
+    }
+
+    /// Queue the next frame of sound
+    fn queue_frame(&mut self, chip: &Chip8, playing: bool) {
+        let samples = self.synth.frame_samples();
+        self.samples.resize(samples, 0.0);
+        self.synth.generate(chip, playing && !self.muted, &mut self.samples);
+        self.queue.queue(&self.samples);
+    }
+
+    /// The number of samples waiting to be played
+    fn queued_samples(&self) -> usize {
+        self.queue.size() as usize / std::mem::size_of::<f32>()
+    }
+}
+
+impl Audio for Buzzer {
+    fn set_playing(&mut self, playing: bool, chip: &Chip8) {
+        let frame = self.queue.spec().freq as usize / 60;
+
+        // the host's frames don't line up exactly with the sound card's
+        // clock, so frames are doubled up or dropped to keep the queue
+        // about the same length
+        if self.queued_samples() > frame * (QUEUED_FRAMES + 2) {
+            return;
+        }
+        self.queue_frame(chip, playing);
+        while self.queued_samples() < frame * QUEUED_FRAMES {
+            self.queue_frame(chip, playing);
         }
     }
 }
@@ -0,0 +1,286 @@
+use std::io::{ self, BufRead, Write };
+
+use chip8::{ Chip8, MEMORY_SIZE };
+use chip8::debugger::{ Condition, Debugger, Register, Watchpoint };
+use chip8::disasm::{ self, Syntax };
+
+const HELP: &str = "\
+Commands (numbers are hexadecimal):
+  b, break <addr>             stop before the instruction at addr
+  d, delete <addr>            remove the breakpoint at addr
+  w, watch <addr> [len] [r|w|rw]
+                              stop before instructions that access memory
+  unwatch <addr>              remove the watchpoints starting at addr
+  cond <reg> changed|<value>  stop after a register changes or takes a value
+  uncond <reg>                remove the conditions on a register
+  info                        list breakpoints, watchpoints and conditions
+  s, step                     execute one instruction
+  n, next                     execute one instruction, running calls to completion
+  finish                      run until the current subroutine returns
+  u, until <addr>             run until the program counter reaches addr
+  r, regs                     show the registers and stack
+  m, mem <addr> [len]         show the contents of memory
+  dis, disasm [addr] [count]  disassemble instructions, from the pc by default
+  c, continue                 resume the program
+  q, quit                     exit the emulator
+";
+
+/// Read debugger commands from stdin until one of them resumes the program.
+///
+/// Returns false if the emulator should quit. If stdin is closed, the
+/// program simply resumes.
+pub fn run(chip: &mut Chip8, debugger: &mut Debugger) -> bool {
+    let stdin = io::stdin();
+    show_location(chip, debugger);
+
+    loop {
+        print!("(chip8) ");
+        let _ = io::stdout().flush();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                debugger.resume();
+                return true;
+            },
+            Ok(_) => (),
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+
+        let result = match command {
+            "b" | "break" => address(args.first()).map(|address| {
+                debugger.add_breakpoint(address);
+                println!("Breakpoint at 0x{:03X}", address);
+            }),
+            "d" | "delete" => address(args.first()).map(|address| {
+                if !debugger.remove_breakpoint(address) {
+                    println!("No breakpoint at 0x{:03X}", address);
+                }
+            }),
+            "w" | "watch" => watchpoint(args).and_then(|watchpoint| {
+                if !debugger.add_watchpoint(watchpoint) {
+                    return Err("the watched range goes past the end of memory".to_string());
+                }
+                println!("Watching 0x{:03X} - 0x{:03X}", watchpoint.address,
+                         watchpoint.address + watchpoint.len - 1);
+                Ok(())
+            }),
+            "unwatch" => number(args.first()).map(|address| {
+                if !debugger.remove_watchpoint(address) {
+                    println!("No watchpoint at 0x{:03X}", address);
+                }
+            }),
+            "cond" => condition(args).map(|(register, condition)| {
+                debugger.add_condition(register, condition);
+            }),
+            "uncond" => register(args.first()).map(|register| {
+                if !debugger.remove_conditions(register) {
+                    println!("No condition on {}", register);
+                }
+            }),
+            "info" => {
+                show_info(debugger);
+                Ok(())
+            },
+            "s" | "step" => {
+                debugger.step();
+                return true;
+            },
+            "n" | "next" => {
+                debugger.step_over(chip);
+                return true;
+            },
+            "finish" => {
+                if chip.get_sp() == 0 {
+                    Err("not in a subroutine".to_string())
+                } else {
+                    debugger.step_out(chip);
+                    return true;
+                }
+            },
+            "u" | "until" => match address(args.first()) {
+                Ok(address) => {
+                    debugger.run_to(address);
+                    return true;
+                },
+                Err(err) => Err(err),
+            },
+            "r" | "regs" => {
+                show_registers(chip);
+                Ok(())
+            },
+            "m" | "mem" => number(args.first()).and_then(|address| {
+                let len = match args.get(1) {
+                    Some(_) => number(args.get(1))?,
+                    None => 0x40,
+                };
+                show_memory(chip, address, len);
+                Ok(())
+            }),
+            "dis" | "disasm" => {
+                let address = match args.first() {
+                    Some(_) => address(args.first()),
+                    None => Ok(chip.get_pc()),
+                };
+                let count = match args.get(1) {
+                    Some(_) => number(args.get(1)),
+                    None => Ok(10),
+                };
+                address.and_then(|address| count.map(|count| {
+                    show_disassembly(chip, debugger, address, count);
+                }))
+            },
+            "c" | "continue" => {
+                debugger.resume();
+                return true;
+            },
+            "q" | "quit" => return false,
+            "h" | "help" => {
+                print!("{}", HELP);
+                Ok(())
+            },
+            _ => Err(format!("unknown command '{}', try 'help'", command)),
+        };
+
+        if let Err(err) = result {
+            println!("{}", err);
+        }
+    }
+}
+
+/// Print the address and disassembly of the next instruction
+pub fn show_location(chip: &Chip8, debugger: &Debugger) {
+    show_disassembly(chip, debugger, chip.get_pc(), 1);
+}
+
+fn show_registers(chip: &Chip8) {
+    for row in 0 .. 2 {
+        let registers: Vec<String> = (row * 8 .. row * 8 + 8)
+            .map(|register| format!("V{:X}={:02X}", register, chip.get_v(register)))
+            .collect();
+        println!("{}", registers.join(" "));
+    }
+    println!("I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+             chip.get_i(), chip.get_pc(), chip.get_sp(), chip.get_dt(), chip.get_st());
+
+    let stack: Vec<String> = chip.get_stack().iter()
+        .map(|address| format!("{:04X}", address))
+        .collect();
+    println!("Stack: {}", stack.join(" "));
+}
+
+fn show_memory(chip: &Chip8, address: usize, len: usize) {
+    let mem = chip.get_mem();
+    let end = std::cmp::min(address.saturating_add(len), mem.len());
+    let mut line = address;
+    while line < end {
+        let bytes: Vec<String> = mem[line .. std::cmp::min(line + 0x10, end)].iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        println!("{:04X}: {}", line, bytes.join(" "));
+        line += 0x10;
+    }
+}
+
+fn show_disassembly(chip: &Chip8, debugger: &Debugger, address: u16, count: usize) {
+    let mem = chip.get_mem();
+    let breakpoints = debugger.breakpoints();
+    let mut address = address as usize;
+    for _ in 0 .. count {
+        if address >= mem.len() {
+            break;
+        }
+        let (text, size) = disasm::disassemble_at(&mem[address ..], Syntax::Cowgod);
+        let marker = if address == chip.get_pc() as usize { '>' } else { ' ' };
+        let breakpoint = if breakpoints.contains(&(address as u16)) { '*' } else { ' ' };
+        println!("{}{} {:04X}: {}", marker, breakpoint, address, text);
+        address += size;
+    }
+}
+
+fn show_info(debugger: &Debugger) {
+    for address in debugger.breakpoints() {
+        println!("Breakpoint at 0x{:03X}", address);
+    }
+    for watchpoint in debugger.watchpoints() {
+        let access = match (watchpoint.read, watchpoint.write) {
+            (true, true) => "rw",
+            (true, false) => "r",
+            _ => "w",
+        };
+        println!("Watchpoint ({}) at 0x{:03X} - 0x{:03X}", access, watchpoint.address,
+                 watchpoint.address + watchpoint.len - 1);
+    }
+    for &(register, condition) in debugger.conditions() {
+        match condition {
+            Condition::Changed => println!("Condition {} changed", register),
+            Condition::Equals(value) => println!("Condition {} == 0x{:X}", register, value),
+        }
+    }
+}
+
+/// Parse a hexadecimal number, with or without a 0x, # or $ prefix
+fn number(arg: Option<&&str>) -> Result<usize, String> {
+    let arg = arg.ok_or_else(|| "missing argument, try 'help'".to_string())?;
+    let digits = arg.trim_start_matches("0x").trim_start_matches('#').trim_start_matches('$');
+    usize::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a hexadecimal number", arg))
+}
+
+fn address(arg: Option<&&str>) -> Result<u16, String> {
+    let value = number(arg)?;
+    if value > 0xffff {
+        return Err(format!("address 0x{:X} is out of range", value));
+    }
+    Ok(value as u16)
+}
+
+fn register(arg: Option<&&str>) -> Result<Register, String> {
+    let arg = arg.ok_or_else(|| "missing register, try 'help'".to_string())?;
+    let name = arg.to_ascii_lowercase();
+    match name.as_str() {
+        "i" => Ok(Register::I),
+        "pc" => Ok(Register::Pc),
+        "sp" => Ok(Register::Sp),
+        "dt" => Ok(Register::Dt),
+        "st" => Ok(Register::St),
+        _ if name.len() == 2 && name.starts_with('v') => u8::from_str_radix(&name[1 ..], 16)
+            .map(Register::V)
+            .map_err(|_| format!("unknown register '{}'", arg)),
+        _ => Err(format!("unknown register '{}'", arg)),
+    }
+}
+
+fn condition(args: &[&str]) -> Result<(Register, Condition), String> {
+    let register = register(args.first())?;
+    let condition = match args.get(1) {
+        Some(&"changed") => Condition::Changed,
+        arg => Condition::Equals(number(arg)? as u16),
+    };
+    Ok((register, condition))
+}
+
+fn watchpoint(args: &[&str]) -> Result<Watchpoint, String> {
+    let address = number(args.first())?;
+    let len = match args.get(1) {
+        Some(_) => number(args.get(1))?,
+        None => 1,
+    };
+    if len == 0 {
+        return Err("cannot watch 0 bytes".to_string());
+    }
+    if address.checked_add(len).map_or(true, |end| end > MEMORY_SIZE) {
+        return Err("the watched range goes past the end of memory".to_string());
+    }
+    let (read, write) = match args.get(2) {
+        None | Some(&"rw") => (true, true),
+        Some(&"r") => (true, false),
+        Some(&"w") => (false, true),
+        Some(arg) => return Err(format!("unknown access '{}', expected r, w or rw", arg)),
+    };
+    Ok(Watchpoint { address, len, read, write })
+}
@@ -0,0 +1,199 @@
+extern crate sdl2;
+
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::{ Button, GameController };
+use sdl2::event::Event;
+
+use std::fs::File;
+use std::io::Read;
+
+/// A controller button that presses a keypad key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Binding {
+    /// The player whose controller it is, counting from 0, or None for
+    /// every controller
+    player: Option<usize>,
+    button: Button,
+    key: usize,
+}
+
+/// Which controller buttons press each key of the Chip8 hex keypad
+#[derive(Debug, Clone)]
+pub struct ControllerProfile {
+    bindings: Vec<Binding>,
+}
+
+impl Default for ControllerProfile {
+    /// The D-pad on the 5 7 8 9 cross most games use for directions, the
+    /// face buttons on the keys around it, and the shoulders and menu
+    /// buttons on the corners of the keypad. Every controller presses the
+    /// same keys.
+    fn default() -> ControllerProfile {
+        let layout = [
+            (Button::DPadUp, 0x5), (Button::DPadLeft, 0x7), (Button::DPadDown, 0x8), (Button::DPadRight, 0x9),
+            (Button::A, 0x6), (Button::B, 0x4), (Button::X, 0xa), (Button::Y, 0xb),
+            (Button::LeftShoulder, 0x1), (Button::RightShoulder, 0xc),
+            (Button::Back, 0x0), (Button::Start, 0xf),
+        ];
+        ControllerProfile {
+            bindings: layout.iter()
+                .map(|&(button, key)| Binding { player: None, button, key })
+                .collect(),
+        }
+    }
+}
+
+impl ControllerProfile {
+    /// Change bindings according to the contents of a controller profile.
+    ///
+    /// Each line binds a keypad key, as a hex digit, to one or more buttons
+    /// separated by commas, for example ``5 = dpup, 2:y``. Buttons are named
+    /// as in SDL controller mappings: a, b, x, y, back, guide, start,
+    /// leftstick, rightstick, leftshoulder, rightshoulder, dpup, dpdown,
+    /// dpleft and dpright. A button on its own is read from every
+    /// controller, while a player number before it, as in ``2:y``, reads it
+    /// from that player's controller only. Players are numbered from 1 in
+    /// the order their controllers were connected. A keypad key may be
+    /// given on several lines, which add up. Keypad keys that are not
+    /// mentioned keep their binding. Blank lines and lines starting with
+    /// ``#`` or ``;`` are ignored.
+    pub fn apply(&mut self, source: &str) -> Result<(), String> {
+        // the first line for a key replaces its old binding
+        let mut mentioned = [false; 16];
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let (key, names) = match (usize::from_str_radix(key, 16), parts.next()) {
+                (Ok(key), Some(names)) if key < 16 => (key, names),
+                _ => return Err(format!("line {}: expected '<hex key> = <buttons>'", number + 1)),
+            };
+
+            let mut bindings = Vec::new();
+            for name in names.split(',').map(str::trim) {
+                let binding = parse_button(name)
+                    .map(|(player, button)| Binding { player, button, key })
+                    .map_err(|err| format!("line {}: {}", number + 1, err))?;
+                bindings.push(binding);
+            }
+
+            if !mentioned[key] {
+                mentioned[key] = true;
+                self.bindings.retain(|binding| binding.key != key);
+            }
+            self.bindings.extend(bindings);
+        }
+
+        Ok(())
+    }
+
+    /// Read a controller profile and apply it on top of the current
+    /// bindings
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("{}: {}", path, err))?;
+        self.apply(&source).map_err(|err| format!("{}:{}", path, err))
+    }
+}
+
+/// Parse a button, optionally after a player number and a colon
+fn parse_button(name: &str) -> Result<(Option<usize>, Button), String> {
+    let (player, button) = match name.find(':') {
+        Some(colon) => {
+            let player = match name[.. colon].trim().parse::<usize>() {
+                Ok(player) if player > 0 => player - 1,
+                _ => return Err(format!("'{}' is not a player number", &name[.. colon])),
+            };
+            (Some(player), name[colon + 1 ..].trim())
+        },
+        None => (None, name),
+    };
+
+    match Button::from_string(&button.to_ascii_lowercase()) {
+        Some(button) => Ok((player, button)),
+        None => Err(format!("unknown button '{}'", button)),
+    }
+}
+
+/// The game controllers plugged in, each with the player it was given
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    profile: ControllerProfile,
+
+    /// The controller of each player. A controller that is unplugged
+    /// leaves a gap, which the next one plugged in fills.
+    players: Vec<Option<GameController>>,
+}
+
+impl Controllers {
+    /// Controllers are opened as SDL reports them, including the ones
+    /// already plugged in when the program starts
+    pub fn new(subsystem: GameControllerSubsystem, profile: ControllerProfile) -> Controllers {
+        Controllers {
+            subsystem,
+            profile,
+            players: Vec::new(),
+        }
+    }
+
+    /// Open and close controllers as they are plugged in and unplugged
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded{ which, .. } => {
+                let controller = match self.subsystem.open(which as u32) {
+                    Ok(controller) => controller,
+                    Err(err) => {
+                        println!("[WARNING] could not open controller: {}", err);
+                        return;
+                    },
+                };
+
+                // SDL can report a controller more than once at start up
+                if self.players.iter().flatten().any(|open| open.instance_id() == controller.instance_id()) {
+                    return;
+                }
+
+                let player = self.players.iter().position(Option::is_none).unwrap_or(self.players.len());
+                println!("Controller for player {} connected: {}", player + 1, controller.name());
+                if player == self.players.len() {
+                    self.players.push(Some(controller));
+                } else {
+                    self.players[player] = Some(controller);
+                }
+            },
+
+            Event::ControllerDeviceRemoved{ which, .. } => {
+                for (player, slot) in self.players.iter_mut().enumerate() {
+                    if slot.as_ref().map(|controller| controller.instance_id()) == Some(which) {
+                        println!("Controller for player {} disconnected", player + 1);
+                        *slot = None;
+                    }
+                }
+            },
+
+            _ => (),
+        }
+    }
+
+    /// Mark the keypad keys pressed by any controller button that is held
+    pub fn press_keys(&self, pressed: &mut [bool; 16]) {
+        for (player, controller) in self.players.iter().enumerate() {
+            let controller = match *controller {
+                Some(ref controller) => controller,
+                None => continue,
+            };
+            for binding in self.profile.bindings.iter() {
+                if binding.player.map_or(true, |bound| bound == player) {
+                    pressed[binding.key] |= controller.button(binding.button);
+                }
+            }
+        }
+    }
+}
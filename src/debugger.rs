@@ -0,0 +1,553 @@
+//! Breakpoints, watchpoints and stepping for a running Chip8.
+//!
+//! A ``Debugger`` drives a ``Chip8`` in place of ``Chip8::run_frame()``,
+//! checking its breakpoints before every instruction and its register
+//! conditions after it. Stepping commands don't run anything by themselves,
+//! they set a goal and the next ``run_frame()`` stops once it is reached,
+//! so the frontend keeps feeding input and ticking the timers while a long
+//! step-over or run-to-cursor is in progress.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::mem;
+
+use instruction::Instruction;
+use { Chip8, EmulationError, MEMORY_SIZE };
+
+/// A register of the Chip8 that a condition can watch
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    /// One of the general purpose registers V0 - VF
+    V(u8),
+    /// The index register
+    I,
+    /// The program counter
+    Pc,
+    /// The stack pointer
+    Sp,
+    /// The delay timer
+    Dt,
+    /// The sound timer
+    St,
+}
+
+impl Register {
+    /// Read the current value of the register
+    pub fn value(&self, chip: &Chip8) -> u16 {
+        match *self {
+            Register::V(register) => chip.get_v(register as usize & 0xf) as u16,
+            Register::I => chip.get_i(),
+            Register::Pc => chip.get_pc(),
+            Register::Sp => chip.get_sp() as u16,
+            Register::Dt => chip.get_dt() as u16,
+            Register::St => chip.get_st() as u16,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Register::V(register) => write!(f, "V{:X}", register),
+            Register::I => write!(f, "I"),
+            Register::Pc => write!(f, "PC"),
+            Register::Sp => write!(f, "SP"),
+            Register::Dt => write!(f, "DT"),
+            Register::St => write!(f, "ST"),
+        }
+    }
+}
+
+/// When a register condition stops the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// Stop after any instruction that changes the register
+    Changed,
+
+    /// Stop after an instruction that sets the register to the value
+    Equals(u16),
+}
+
+/// The way an instruction accesses memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Access::Read => write!(f, "reads"),
+            Access::Write => write!(f, "writes"),
+        }
+    }
+}
+
+/// A range of memory that stops the program when it is accessed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    /// The first watched address
+    pub address: usize,
+
+    /// The number of watched bytes
+    pub len: usize,
+
+    /// Stop on instructions that read from the range
+    pub read: bool,
+
+    /// Stop on instructions that write to the range
+    pub write: bool,
+}
+
+/// The reason ``Debugger::run_frame()`` stopped before the end of the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The program counter reached a breakpoint. The instruction there has
+    /// not been executed yet.
+    Breakpoint(u16),
+
+    /// The instruction at ``pc`` is about to access a watched address.
+    Watchpoint { pc: u16, address: usize, access: Access },
+
+    /// The last instruction met a register condition.
+    Condition { register: Register, value: u16 },
+
+    /// A step, step-over, step-out or run-to-cursor finished at ``pc``.
+    Stepped(u16),
+
+    /// The last instruction could not be executed.
+    Error(EmulationError),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StopReason::Breakpoint(pc) =>
+                write!(f, "breakpoint at 0x{:03X}", pc),
+            StopReason::Watchpoint { pc, address, access } =>
+                write!(f, "instruction at 0x{:03X} {} watched address 0x{:03X}", pc, access, address),
+            StopReason::Condition { register, value } =>
+                write!(f, "{} is now 0x{:X}", register, value),
+            StopReason::Stepped(pc) =>
+                write!(f, "stopped at 0x{:03X}", pc),
+            StopReason::Error(error) =>
+                write!(f, "{}", error),
+        }
+    }
+}
+
+/// Where a stepping command should stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Goal {
+    /// After the next instruction
+    Step,
+
+    /// When the subroutine call at the start of the step returns
+    StepOver { address: u16, sp: u8 },
+
+    /// When the current subroutine returns
+    StepOut { sp: u8 },
+
+    /// When the program counter reaches the address
+    RunTo(u16),
+}
+
+/// Runs a Chip8 under the control of breakpoints, watchpoints, register
+/// conditions and stepping commands.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<(Register, Condition)>,
+
+    /// The pending stepping command, if any
+    goal: Option<Goal>,
+
+    /// Set when resuming, so the breakpoint the program stopped at doesn't
+    /// stop it again straight away. It stays set until the program counter
+    /// moves on from that instruction.
+    resuming: bool,
+
+    /// The number of instructions already executed in the current frame
+    executed: usize,
+
+    /// Whether any instruction in the current frame changed the display
+    rendered: bool,
+}
+
+impl Debugger {
+    /// Create a debugger with no breakpoints
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    /// Stop before executing the instruction at ``address``
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Remove the breakpoint at ``address``, returning false if there was none
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    /// Get the addresses of every breakpoint, in order
+    pub fn breakpoints(&self) -> Vec<u16> {
+        self.breakpoints.iter().cloned().collect()
+    }
+
+    /// Stop before any instruction that accesses the watched range.
+    /// Returns false, without adding it, if the range is empty or goes past
+    /// the end of memory.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let in_memory = watchpoint.address.checked_add(watchpoint.len).is_some_and(|end| end <= MEMORY_SIZE);
+        if watchpoint.len == 0 || !in_memory {
+            return false;
+        }
+        self.watchpoints.push(watchpoint);
+        true
+    }
+
+    /// Remove every watchpoint starting at ``address``, returning false if
+    /// there were none
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|watchpoint| watchpoint.address != address);
+        self.watchpoints.len() != count
+    }
+
+    /// Get every watchpoint, in the order they were added
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Stop after any instruction that meets the condition on ``register``
+    pub fn add_condition(&mut self, register: Register, condition: Condition) {
+        self.conditions.push((register, condition));
+    }
+
+    /// Remove every condition on ``register``, returning false if there
+    /// were none
+    pub fn remove_conditions(&mut self, register: Register) -> bool {
+        let count = self.conditions.len();
+        self.conditions.retain(|&(watched, _)| watched != register);
+        self.conditions.len() != count
+    }
+
+    /// Get every register condition, in the order they were added
+    pub fn conditions(&self) -> &[(Register, Condition)] {
+        &self.conditions
+    }
+
+    /// Run freely until the next breakpoint, watchpoint or condition
+    pub fn resume(&mut self) {
+        self.goal = None;
+        self.resuming = true;
+    }
+
+    /// Stop again after executing a single instruction
+    pub fn step(&mut self) {
+        self.goal = Some(Goal::Step);
+        self.resuming = true;
+    }
+
+    /// Stop after the next instruction, running a subroutine called by it
+    /// (2nnn) to completion
+    pub fn step_over(&mut self, chip: &Chip8) {
+        let pc = chip.get_pc();
+        self.goal = match Instruction::decode(&chip.get_mem()[pc as usize ..]) {
+            Some(Instruction::Call(_)) =>
+                Some(Goal::StepOver { address: pc.wrapping_add(2), sp: chip.get_sp() }),
+            _ => Some(Goal::Step),
+        };
+        self.resuming = true;
+    }
+
+    /// Stop once the current subroutine has returned to its caller
+    pub fn step_out(&mut self, chip: &Chip8) {
+        self.goal = Some(Goal::StepOut { sp: chip.get_sp() });
+        self.resuming = true;
+    }
+
+    /// Stop when the program counter reaches ``address``
+    pub fn run_to(&mut self, address: u16) {
+        self.goal = Some(Goal::RunTo(address));
+        self.resuming = true;
+    }
+
+    /// Performs a single Chip8 operation, unless a breakpoint or watchpoint
+    /// stops the program before it.
+    ///
+    /// Any stop cancels the pending stepping command.
+    pub fn tick(&mut self, chip: &mut Chip8) -> Result<(), StopReason> {
+        let result = self.tick_checked(chip);
+        if result.is_err() {
+            self.goal = None;
+        }
+        result
+    }
+
+    fn tick_checked(&mut self, chip: &mut Chip8) -> Result<(), StopReason> {
+        let pc = chip.get_pc();
+        let resuming = mem::replace(&mut self.resuming, false);
+        if !resuming {
+            if self.breakpoints.contains(&pc) {
+                return Err(StopReason::Breakpoint(pc));
+            }
+            if let Some((address, access)) = self.watched_access(chip) {
+                return Err(StopReason::Watchpoint { pc, address, access });
+            }
+        }
+
+        let before: Vec<u16> = self.conditions.iter()
+            .map(|&(register, _)| register.value(chip))
+            .collect();
+
+        let result = chip.tick();
+        self.executed += 1;
+        self.rendered |= chip.get_render_flag();
+
+        // fx0a and dxyn waiting for the display block by staying on the same
+        // instruction, which shouldn't stop on its breakpoint again until the
+        // program has moved on
+        if resuming && chip.get_pc() == pc {
+            self.resuming = true;
+        }
+        result.map_err(StopReason::Error)?;
+
+        for (&(register, condition), before) in self.conditions.iter().zip(before) {
+            let value = register.value(chip);
+            let met = match condition {
+                Condition::Changed => value != before,
+                Condition::Equals(expected) => value == expected && before != expected,
+            };
+            if met {
+                return Err(StopReason::Condition { register, value });
+            }
+        }
+
+        let pc = chip.get_pc();
+        let reached = match self.goal {
+            None => false,
+            Some(Goal::Step) => true,
+            Some(Goal::StepOver { address, sp }) => pc == address && chip.get_sp() == sp,
+            Some(Goal::StepOut { sp }) => chip.get_sp() < sp,
+            Some(Goal::RunTo(address)) => pc == address,
+        };
+        if reached {
+            return Err(StopReason::Stepped(pc));
+        }
+
+        Ok(())
+    }
+
+    /// Emulates one 60Hz frame like ``Chip8::run_frame()``, returning true
+    /// if the display changed during it.
+    ///
+    /// If the program stops part of the way through, the reason is returned
+    /// and the timers are not ticked. The next call picks the frame up where
+    /// it stopped.
+    pub fn run_frame(&mut self, chip: &mut Chip8, instructions: usize) -> Result<bool, StopReason> {
+        while self.executed < instructions {
+            self.tick(chip)?;
+        }
+        chip.tick_timers();
+        self.executed = 0;
+        Ok(mem::replace(&mut self.rendered, false))
+    }
+
+    /// Find the first watched byte that the instruction at the program
+    /// counter is about to access
+    fn watched_access(&self, chip: &Chip8) -> Option<(usize, Access)> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+
+        let (start, len, access) = memory_access(chip)?;
+        for watchpoint in self.watchpoints.iter() {
+            let watched = match access {
+                Access::Read => watchpoint.read,
+                Access::Write => watchpoint.write,
+            };
+            let first = std::cmp::max(start, watchpoint.address);
+            if watched && first < start.saturating_add(len)
+                && first < watchpoint.address.saturating_add(watchpoint.len)
+            {
+                return Some((first, access));
+            }
+        }
+        None
+    }
+}
+
+/// Work out the range of memory the instruction at the program counter
+/// reads or writes, as a start address, length and kind of access
+fn memory_access(chip: &Chip8) -> Option<(usize, usize, Access)> {
+    let i = chip.get_i() as usize;
+    let instruction = Instruction::decode(&chip.get_mem()[chip.get_pc() as usize ..])?;
+
+    match instruction {
+        Instruction::Draw(_, _, n) => {
+            let (_, len) = chip.sprite_size(n);
+            Some((i, len * chip.get_plane().count_ones() as usize, Access::Read))
+        },
+        Instruction::Bcd(_) => Some((i, 3, Access::Write)),
+        Instruction::Store(x) => Some((i, x as usize + 1, Access::Write)),
+        Instruction::Load(x) => Some((i, x as usize + 1, Access::Read)),
+        Instruction::StoreRange(x, y) =>
+            Some((i, (x as isize - y as isize).unsigned_abs() + 1, Access::Write)),
+        Instruction::LoadRange(x, y) =>
+            Some((i, (x as isize - y as isize).unsigned_abs() + 1, Access::Read)),
+        Instruction::Audio => Some((i, 0x10, Access::Read)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use { asm, Quirks };
+
+    fn chip(source: &str, quirks: Quirks) -> Chip8 {
+        Chip8::with_rom_data(&asm::assemble(source).unwrap(), quirks).unwrap()
+    }
+
+    fn watch(address: usize, len: usize, read: bool, write: bool) -> Watchpoint {
+        Watchpoint { address, len, read, write }
+    }
+
+    #[test]
+    fn stops_at_breakpoints_before_executing_them() {
+        let mut chip = chip("loop: ADD V0, 1 \n JP loop", Quirks::default());
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x202);
+
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Breakpoint(0x202)));
+        assert_eq!(chip.get_v(0), 1);
+
+        // resuming runs past the breakpoint it stopped at
+        debugger.resume();
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Breakpoint(0x202)));
+        assert_eq!(chip.get_v(0), 2);
+
+        assert!(debugger.remove_breakpoint(0x202));
+        debugger.resume();
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+    }
+
+    #[test]
+    fn breakpoints_on_blocking_instructions_stop_once() {
+        let mut chip = chip("start: LD V1, K \n JP start", Quirks::default());
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x200);
+
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Breakpoint(0x200)));
+        debugger.resume();
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+        assert!(chip.is_waiting_for_key());
+
+        // once the key is released the program comes round to it again
+        chip.key_down(0x4);
+        chip.key_up(0x4);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Breakpoint(0x200)));
+        assert_eq!(chip.get_v(1), 0x4);
+
+        // the same goes for sprites waiting for the display
+        let mut chip = self::chip("DRW V0, V0, 5 \n done: JP done", Quirks::vip());
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x200);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Breakpoint(0x200)));
+        debugger.resume();
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+        assert_eq!(chip.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn watchpoints_must_lie_in_memory() {
+        let mut debugger = Debugger::new();
+        assert!(!debugger.add_watchpoint(watch(0x300, 0, true, true)));
+        assert!(!debugger.add_watchpoint(watch(MEMORY_SIZE - 1, 2, true, true)));
+        assert!(!debugger.add_watchpoint(watch(MEMORY_SIZE, 1, true, true)));
+        assert!(!debugger.add_watchpoint(watch(usize::MAX, 2, true, true)));
+        assert!(!debugger.add_watchpoint(watch(2, usize::MAX, true, true)));
+        assert!(debugger.watchpoints().is_empty());
+
+        assert!(debugger.add_watchpoint(watch(MEMORY_SIZE - 1, 1, true, true)));
+        assert_eq!(debugger.watchpoints().len(), 1);
+    }
+
+    #[test]
+    fn watchpoints_at_the_end_of_memory() {
+        let mut chip = chip("LD I, long #FFFE \n LD [I], V1", Quirks::xochip());
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(watch(MEMORY_SIZE - 1, 1, false, true));
+
+        let stop = StopReason::Watchpoint { pc: 0x204, address: MEMORY_SIZE - 1, access: Access::Write };
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(stop));
+        assert_eq!(chip.get_pc(), 0x204);
+    }
+
+    #[test]
+    fn watchpoints_stop_on_bcd_and_register_stores() {
+        let source = "LD I, #300 \n LD V0, 123 \n LD B, V0 \n LD [I], V3 \n LD V3, [I] \n done: JP done";
+        let mut chip = chip(source, Quirks::default());
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(watch(0x302, 1, false, true));
+        debugger.add_watchpoint(watch(0x304, 4, false, true));
+
+        assert_eq!(debugger.run_frame(&mut chip, 15),
+                   Err(StopReason::Watchpoint { pc: 0x204, address: 0x302, access: Access::Write }));
+        assert_eq!(chip.get_mem()[0x302], 0);
+
+        debugger.resume();
+        assert_eq!(debugger.run_frame(&mut chip, 15),
+                   Err(StopReason::Watchpoint { pc: 0x206, address: 0x302, access: Access::Write }));
+        assert_eq!(&chip.get_mem()[0x300 .. 0x303], &[1, 2, 3]);
+
+        // the load only reads, and the store didn't reach 0x304
+        debugger.resume();
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+        assert_eq!(chip.get_pc(), 0x20a);
+    }
+
+    #[test]
+    fn conditions_stop_after_the_register_changes() {
+        let mut chip = chip("loop: ADD V0, 1 \n JP loop", Quirks::default());
+        let mut debugger = Debugger::new();
+        debugger.add_condition(Register::V(0), Condition::Equals(3));
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Condition { register: Register::V(0), value: 3 }));
+        assert_eq!(chip.get_pc(), 0x202);
+
+        assert!(debugger.remove_conditions(Register::V(0)));
+        debugger.add_condition(Register::I, Condition::Changed);
+        assert!(debugger.run_frame(&mut chip, 15).is_ok());
+    }
+
+    #[test]
+    fn steps_over_and_out_of_subroutines() {
+        let source = "CALL sub \n LD V0, 1 \n done: JP done \n sub: LD V1, 2 \n LD V2, 3 \n RET";
+        let mut chip = chip(source, Quirks::default());
+        let mut debugger = Debugger::new();
+
+        debugger.step_over(&chip);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Stepped(0x202)));
+        assert_eq!((chip.get_v(1), chip.get_v(2), chip.get_sp()), (2, 3, 0));
+
+        debugger.step_over(&chip);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Stepped(0x204)));
+
+        let mut chip = self::chip(source, Quirks::default());
+        debugger.step();
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Stepped(0x206)));
+        debugger.step_out(&chip);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Stepped(0x202)));
+        assert_eq!(chip.get_v(2), 3);
+
+        debugger.run_to(0x204);
+        assert_eq!(debugger.run_frame(&mut chip, 15), Err(StopReason::Stepped(0x204)));
+        assert_eq!(chip.get_v(0), 1);
+    }
+}
@@ -0,0 +1,406 @@
+//! Turns Chip8 machine code back into readable assembly.
+//!
+//! Two syntaxes are supported: the mnemonics from Cowgod's Chip-8 technical
+//! reference (``LD V0, #05``), and the syntax of the Octo assembler
+//! (``v0 := 0x05``). Cowgod listings can be fed back into the ``asm`` module.
+
+use std::collections::{ BTreeMap, BTreeSet };
+
+use instruction::Instruction;
+use PROGRAM_START;
+
+/// The assembly syntax to produce
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// Mnemonics from Cowgod's Chip-8 technical reference
+    #[default]
+    Cowgod,
+
+    /// The syntax of the Octo assembler
+    Octo,
+}
+
+/// Disassemble a single opcode.
+///
+/// Opcodes that aren't valid instructions are shown as data. The XO-CHIP
+/// long load (f000 nnnn) needs the word after it, so it can only be
+/// disassembled with ``disassemble_at()``.
+pub fn disassemble(opcode: u16, syntax: Syntax) -> String {
+    disassemble_at(&[(opcode >> 8) as u8, opcode as u8], syntax).0
+}
+
+/// Disassemble the instruction at the start of ``bytes``, returning its text
+/// and its size in bytes.
+pub fn disassemble_at(bytes: &[u8], syntax: Syntax) -> (String, usize) {
+    match Instruction::decode(bytes) {
+        Some(instruction) => (format_instruction(&instruction, syntax, &|address| {
+            format_address(address, syntax)
+        }), instruction.size()),
+        None => {
+            let size = bytes.len().min(2);
+            (format_data(&bytes[.. size], syntax), size)
+        },
+    }
+}
+
+/// The kind of label generated for an address in a ROM listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    /// The address is loaded into the i register
+    Data,
+
+    /// The address is the target of a jump
+    Jump,
+
+    /// The address is the target of a call
+    Subroutine,
+}
+
+/// Disassemble a whole ROM into a listing, as it would be loaded at 0x200.
+///
+/// The code is found by following the control flow from the entry point,
+/// so any bytes that can't be reached are shown as data. Jump targets,
+/// subroutines and addresses loaded into i are given labels, and every line
+/// ends with a comment holding its address and raw bytes.
+pub fn disassemble_rom(rom: &[u8], syntax: Syntax) -> String {
+    let origin = PROGRAM_START;
+    let end = origin + rom.len();
+    let bytes_at = |address: usize| &rom[address - origin ..];
+
+    // follow the control flow to find out which bytes are code
+    let mut code: BTreeMap<usize, Instruction> = BTreeMap::new();
+    let mut targets: BTreeMap<usize, LabelKind> = BTreeMap::new();
+    let mut pending = vec![origin];
+
+    while let Some(address) = pending.pop() {
+        if address < origin || address >= end || code.contains_key(&address) {
+            continue;
+        }
+        let instruction = match Instruction::decode(bytes_at(address)) {
+            Some(Instruction::Sys(_)) | None => continue,
+            Some(instruction) => instruction,
+        };
+        code.insert(address, instruction);
+
+        let mut add_target = |target: u16, kind: LabelKind| {
+            let target = targets.entry(target as usize).or_insert(kind);
+            *target = (*target).max(kind);
+        };
+
+        let next = address + instruction.size();
+        match instruction {
+            Instruction::Jump(target) => {
+                add_target(target, LabelKind::Jump);
+                pending.push(target as usize);
+            },
+            Instruction::Call(target) => {
+                add_target(target, LabelKind::Subroutine);
+                pending.push(target as usize);
+                pending.push(next);
+            },
+            // the target depends on v0, so it can't be followed
+            Instruction::JumpOffset(target) => add_target(target, LabelKind::Jump),
+            Instruction::Return | Instruction::Exit => (),
+            Instruction::LoadI(target) | Instruction::LoadILong(target) => {
+                add_target(target, LabelKind::Data);
+                pending.push(next);
+            },
+            _ if instruction.is_skip() => {
+                pending.push(next);
+                let skipped = if next + 1 < end && bytes_at(next)[.. 2] == [0xf0, 0x00] { 4 } else { 2 };
+                pending.push(next + skipped);
+            },
+            _ => pending.push(next),
+        }
+    }
+
+    // only give labels to addresses where a line starts
+    let mut covered = BTreeSet::new();
+    for (address, instruction) in code.iter() {
+        covered.extend(address + 1 .. address + instruction.size());
+    }
+    let labels: BTreeMap<usize, String> = targets.iter()
+        .filter(|&(address, _)| *address >= origin && *address < end && !covered.contains(address))
+        .map(|(&address, kind)| {
+            let prefix = match *kind {
+                LabelKind::Data => "data",
+                LabelKind::Jump => "label",
+                LabelKind::Subroutine => "sub",
+            };
+            (address, format!("{}_{:03X}", prefix, address))
+        })
+        .collect();
+    let address_name = |address: u16| {
+        labels.get(&(address as usize))
+            .cloned()
+            .unwrap_or_else(|| format_address(address, syntax))
+    };
+
+    let mut listing = String::new();
+    let mut address = origin;
+    while address < end {
+        if let Some(label) = labels.get(&address) {
+            match syntax {
+                Syntax::Cowgod => listing.push_str(&format!("{}:\n", label)),
+                Syntax::Octo => listing.push_str(&format!(": {}\n", label)),
+            }
+        }
+
+        let (text, size) = match code.get(&address) {
+            Some(instruction) => {
+                (format_instruction(instruction, syntax, &address_name), instruction.size())
+            },
+            None => {
+                // data runs until the next line of code or label, 8 bytes at most
+                let mut size = 1;
+                while size < 8 && address + size < end
+                    && !code.contains_key(&(address + size))
+                    && !labels.contains_key(&(address + size))
+                {
+                    size += 1;
+                }
+                (format_data(&bytes_at(address)[.. size], syntax), size)
+            },
+        };
+        let size = size.min(end - address);
+
+        let raw: String = bytes_at(address)[.. size].iter().map(|byte| format!("{:02X}", byte)).collect();
+        let comment = match syntax {
+            Syntax::Cowgod => ';',
+            Syntax::Octo => '#',
+        };
+        listing.push_str(&format!("    {:<27} {} {:04X}: {}\n", text, comment, address, raw));
+
+        address += size;
+    }
+
+    listing
+}
+
+/// Format raw bytes that aren't code
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("#{:02X}", byte)).collect();
+            format!("DB {}", bytes.join(", "))
+        },
+        Syntax::Octo => {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+            bytes.join(" ")
+        },
+    }
+}
+
+/// Format an address that has no label
+fn format_address(address: u16, syntax: Syntax) -> String {
+    match syntax {
+        Syntax::Cowgod => format!("#{:03X}", address),
+        Syntax::Octo => format!("0x{:03X}", address),
+    }
+}
+
+/// Format a single instruction, using ``address_name`` to show the targets
+/// of jumps, calls and loads into i.
+fn format_instruction(instruction: &Instruction, syntax: Syntax, address_name: &dyn Fn(u16) -> String) -> String {
+    match syntax {
+        Syntax::Cowgod => format_cowgod(instruction, address_name),
+        Syntax::Octo => format_octo(instruction, address_name),
+    }
+}
+
+fn format_cowgod(instruction: &Instruction, address_name: &dyn Fn(u16) -> String) -> String {
+    use instruction::Instruction::*;
+
+    match *instruction {
+        Sys(address) => format!("SYS {}", address_name(address)),
+        ScrollDown(n) => format!("SCD #{:X}", n),
+        ScrollUp(n) => format!("SCU #{:X}", n),
+        Clear => "CLS".to_string(),
+        Return => "RET".to_string(),
+        ScrollRight => "SCR".to_string(),
+        ScrollLeft => "SCL".to_string(),
+        Exit => "EXIT".to_string(),
+        Lores => "LOW".to_string(),
+        Hires => "HIGH".to_string(),
+        Jump(address) => format!("JP {}", address_name(address)),
+        Call(address) => format!("CALL {}", address_name(address)),
+        SkipEqualByte(x, byte) => format!("SE V{:X}, #{:02X}", x, byte),
+        SkipNotEqualByte(x, byte) => format!("SNE V{:X}, #{:02X}", x, byte),
+        SkipEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        StoreRange(x, y) => format!("LD [I], V{:X}-V{:X}", x, y),
+        LoadRange(x, y) => format!("LD V{:X}-V{:X}, [I]", x, y),
+        LoadByte(x, byte) => format!("LD V{:X}, #{:02X}", x, byte),
+        AddByte(x, byte) => format!("ADD V{:X}, #{:02X}", x, byte),
+        Move(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        Add(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        SubReverse(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        SkipNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        LoadI(address) => format!("LD I, {}", address_name(address)),
+        JumpOffset(address) => format!("JP V0, {}", address_name(address)),
+        Random(x, byte) => format!("RND V{:X}, #{:02X}", x, byte),
+        Draw(x, y, n) => format!("DRW V{:X}, V{:X}, #{:X}", x, y, n),
+        SkipKey(x) => format!("SKP V{:X}", x),
+        SkipNotKey(x) => format!("SKNP V{:X}", x),
+        LoadILong(address) => format!("LD I, LONG {}", address_name(address)),
+        Plane(n) => format!("PLANE #{:X}", n),
+        Audio => "AUDIO".to_string(),
+        GetDelay(x) => format!("LD V{:X}, DT", x),
+        WaitKey(x) => format!("LD V{:X}, K", x),
+        SetDelay(x) => format!("LD DT, V{:X}", x),
+        SetSound(x) => format!("LD ST, V{:X}", x),
+        AddI(x) => format!("ADD I, V{:X}", x),
+        Font(x) => format!("LD F, V{:X}", x),
+        BigFont(x) => format!("LD HF, V{:X}", x),
+        Bcd(x) => format!("LD B, V{:X}", x),
+        Pitch(x) => format!("LD PITCH, V{:X}", x),
+        Store(x) => format!("LD [I], V{:X}", x),
+        Load(x) => format!("LD V{:X}, [I]", x),
+        StoreFlags(x) => format!("LD R, V{:X}", x),
+        LoadFlags(x) => format!("LD V{:X}, R", x),
+    }
+}
+
+fn format_octo(instruction: &Instruction, address_name: &dyn Fn(u16) -> String) -> String {
+    use instruction::Instruction::*;
+
+    match *instruction {
+        // Octo has no way to call machine code routines
+        Sys(_) => format_data(&instruction.encode(), Syntax::Octo),
+        ScrollDown(n) => format!("scroll-down {}", n),
+        ScrollUp(n) => format!("scroll-up {}", n),
+        Clear => "clear".to_string(),
+        Return => "return".to_string(),
+        ScrollRight => "scroll-right".to_string(),
+        ScrollLeft => "scroll-left".to_string(),
+        Exit => "exit".to_string(),
+        Lores => "lores".to_string(),
+        Hires => "hires".to_string(),
+        Jump(address) => format!("jump {}", address_name(address)),
+        Call(address) => format!(":call {}", address_name(address)),
+        SkipEqualByte(x, byte) => format!("if v{:x} != 0x{:02X} then", x, byte),
+        SkipNotEqualByte(x, byte) => format!("if v{:x} == 0x{:02X} then", x, byte),
+        SkipEqual(x, y) => format!("if v{:x} != v{:x} then", x, y),
+        StoreRange(x, y) => format!("save v{:x} - v{:x}", x, y),
+        LoadRange(x, y) => format!("load v{:x} - v{:x}", x, y),
+        LoadByte(x, byte) => format!("v{:x} := 0x{:02X}", x, byte),
+        AddByte(x, byte) => format!("v{:x} += 0x{:02X}", x, byte),
+        Move(x, y) => format!("v{:x} := v{:x}", x, y),
+        Or(x, y) => format!("v{:x} |= v{:x}", x, y),
+        And(x, y) => format!("v{:x} &= v{:x}", x, y),
+        Xor(x, y) => format!("v{:x} ^= v{:x}", x, y),
+        Add(x, y) => format!("v{:x} += v{:x}", x, y),
+        Sub(x, y) => format!("v{:x} -= v{:x}", x, y),
+        ShiftRight(x, y) => format!("v{:x} >>= v{:x}", x, y),
+        SubReverse(x, y) => format!("v{:x} =- v{:x}", x, y),
+        ShiftLeft(x, y) => format!("v{:x} <<= v{:x}", x, y),
+        SkipNotEqual(x, y) => format!("if v{:x} == v{:x} then", x, y),
+        LoadI(address) => format!("i := {}", address_name(address)),
+        JumpOffset(address) => format!("jump0 {}", address_name(address)),
+        Random(x, byte) => format!("v{:x} := random 0x{:02X}", x, byte),
+        Draw(x, y, n) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        SkipKey(x) => format!("if v{:x} -key then", x),
+        SkipNotKey(x) => format!("if v{:x} key then", x),
+        LoadILong(address) => format!("i := long {}", address_name(address)),
+        Plane(n) => format!("plane {}", n),
+        Audio => "audio".to_string(),
+        GetDelay(x) => format!("v{:x} := delay", x),
+        WaitKey(x) => format!("v{:x} := key", x),
+        SetDelay(x) => format!("delay := v{:x}", x),
+        SetSound(x) => format!("buzzer := v{:x}", x),
+        AddI(x) => format!("i += v{:x}", x),
+        Font(x) => format!("i := hex v{:x}", x),
+        BigFont(x) => format!("i := bighex v{:x}", x),
+        Bcd(x) => format!("bcd v{:x}", x),
+        Pitch(x) => format!("pitch := v{:x}", x),
+        Store(x) => format!("save v{:x}", x),
+        Load(x) => format!("load v{:x}", x),
+        StoreFlags(x) => format!("saveflags v{:x}", x),
+        LoadFlags(x) => format!("loadflags v{:x}", x),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm;
+
+    /// Clears the screen, calls a subroutine and loops forever, followed by
+    /// a sprite
+    const ROM: [u8; 13] = [0x00, 0xe0, 0xa2, 0x0a, 0x22, 0x08, 0x12, 0x06, 0x00, 0xee, 0x3c, 0x18, 0xff];
+
+    #[test]
+    fn lists_a_rom_in_cowgod_syntax() {
+        assert_eq!(disassemble_rom(&ROM, Syntax::Cowgod), "    CLS                         ; 0200: 00E0
+    LD I, data_20A              ; 0202: A20A
+    CALL sub_208                ; 0204: 2208
+label_206:
+    JP label_206                ; 0206: 1206
+sub_208:
+    RET                         ; 0208: 00EE
+data_20A:
+    DB #3C, #18, #FF            ; 020A: 3C18FF
+");
+        assert_eq!(asm::assemble(&disassemble_rom(&ROM, Syntax::Cowgod)), Ok(ROM.to_vec()));
+    }
+
+    #[test]
+    fn lists_a_rom_in_octo_syntax() {
+        assert_eq!(disassemble_rom(&ROM, Syntax::Octo), "    clear                       # 0200: 00E0
+    i := data_20A               # 0202: A20A
+    :call sub_208               # 0204: 2208
+: label_206
+    jump label_206              # 0206: 1206
+: sub_208
+    return                      # 0208: 00EE
+: data_20A
+    0x3C 0x18 0xFF              # 020A: 3C18FF
+");
+    }
+
+    #[test]
+    fn octo_skips_read_as_the_opposite_condition() {
+        assert_eq!(disassemble(0x3a05, Syntax::Octo), "if va != 0x05 then");
+        assert_eq!(disassemble(0x4a05, Syntax::Octo), "if va == 0x05 then");
+        assert_eq!(disassemble(0x5ab0, Syntax::Octo), "if va != vb then");
+        assert_eq!(disassemble(0x9ab0, Syntax::Octo), "if va == vb then");
+        assert_eq!(disassemble(0xea9e, Syntax::Octo), "if va -key then");
+        assert_eq!(disassemble(0xeaa1, Syntax::Octo), "if va key then");
+        assert_eq!(disassemble(0x3a05, Syntax::Cowgod), "SE VA, #05");
+    }
+
+    #[test]
+    fn bytes_after_an_unconditional_jump_are_data() {
+        let rom = [0x60, 0x01, 0x12, 0x00, 0x60, 0x02, 0xff];
+        assert_eq!(disassemble_rom(&rom, Syntax::Cowgod), "\
+label_200:
+    LD V0, #01                  ; 0200: 6001
+    JP label_200                ; 0202: 1200
+    DB #60, #02, #FF            ; 0204: 6002FF
+");
+    }
+
+    #[test]
+    fn long_loads_take_four_bytes() {
+        assert_eq!(disassemble_at(&[0xf0, 0x00, 0x12, 0x34], Syntax::Cowgod), ("LD I, LONG #1234".to_string(), 4));
+        assert_eq!(disassemble_at(&[0xf0, 0x00, 0x12, 0x34], Syntax::Octo), ("i := long 0x1234".to_string(), 4));
+
+        // without the word after it, it's only data
+        assert_eq!(disassemble(0xf000, Syntax::Cowgod), "DB #F0, #00");
+        assert_eq!(disassemble(0xf000, Syntax::Octo), "0xF0 0x00");
+
+        // a skip jumps over all four bytes
+        let rom = [0x30, 0x01, 0xf0, 0x00, 0x12, 0x34, 0x12, 0x00];
+        assert_eq!(disassemble_rom(&rom, Syntax::Octo), "\
+: label_200
+    if v0 != 0x01 then          # 0200: 3001
+    i := long 0x1234            # 0202: F0001234
+    jump label_200              # 0206: 1200
+");
+    }
+}
@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fmt;
+
+/// An error raised by the Chip8 while executing an instruction.
+///
+/// Every variant carries the address of the instruction that caused it
+/// in ``pc``. When an error is returned, the program counter has already
+/// moved past the offending instruction, so calling ``tick()`` again will
+/// skip it and carry on. The exception is ``PcOutOfBounds``, where no
+/// instruction could be fetched and the program counter is left alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulationError {
+    /// The opcode does not match any supported instruction.
+    UnknownOpcode { opcode: u16, pc: u16 },
+
+    /// A subroutine was called while all 16 stack slots were in use.
+    StackOverflow { opcode: u16, pc: u16 },
+
+    /// A return was executed while the stack was empty.
+    StackUnderflow { opcode: u16, pc: u16 },
+
+    /// An instruction tried to read or write past the end of RAM.
+    MemoryOutOfBounds { opcode: u16, pc: u16, address: usize },
+
+    /// The program counter points outside of RAM, so no opcode can be fetched.
+    PcOutOfBounds { pc: u16 },
+}
+
+impl EmulationError {
+    /// The address of the instruction that caused the error
+    pub fn pc(&self) -> u16 {
+        match *self {
+            EmulationError::UnknownOpcode { pc, .. } => pc,
+            EmulationError::StackOverflow { pc, .. } => pc,
+            EmulationError::StackUnderflow { pc, .. } => pc,
+            EmulationError::MemoryOutOfBounds { pc, .. } => pc,
+            EmulationError::PcOutOfBounds { pc } => pc,
+        }
+    }
+
+    /// The opcode that caused the error, if one could be fetched
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            EmulationError::UnknownOpcode { opcode, .. } => Some(opcode),
+            EmulationError::StackOverflow { opcode, .. } => Some(opcode),
+            EmulationError::StackUnderflow { opcode, .. } => Some(opcode),
+            EmulationError::MemoryOutOfBounds { opcode, .. } => Some(opcode),
+            EmulationError::PcOutOfBounds { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EmulationError::UnknownOpcode { opcode, pc } =>
+                write!(f, "opcode 0x{:04X} at pc 0x{:03X} is unsupported", opcode, pc),
+            EmulationError::StackOverflow { opcode, pc } =>
+                write!(f, "stack overflow by opcode 0x{:04X} at pc 0x{:03X}", opcode, pc),
+            EmulationError::StackUnderflow { opcode, pc } =>
+                write!(f, "stack underflow by opcode 0x{:04X} at pc 0x{:03X}", opcode, pc),
+            EmulationError::MemoryOutOfBounds { opcode, pc, address } =>
+                write!(f, "opcode 0x{:04X} at pc 0x{:03X} accessed out of bounds address 0x{:X}",
+                       opcode, pc, address),
+            EmulationError::PcOutOfBounds { pc } =>
+                write!(f, "program counter 0x{:X} is out of bounds", pc),
+        }
+    }
+}
+
+impl Error for EmulationError {}
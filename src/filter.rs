@@ -0,0 +1,252 @@
+//! Filters that smooth out the flicker of sprites drawn with XOR.
+//!
+//! Filters work on plain buffers of pixel values, as returned by
+//! ``Chip8::get_pixel_color()``, and turn them into how brightly each of
+//! the two bitplanes is lit, from 0 to 1. ``mix()`` turns those levels into
+//! a colour from a palette.
+//!
+//! A filter has to be fed every frame, whether or not the display changed,
+//! since its output keeps changing as old frames fade away.
+
+use std::collections::VecDeque;
+
+use palette::{ Palette, Rgb };
+
+/// How the frames shown are combined
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterMode {
+    /// Show every frame as it is
+    None,
+
+    /// Pixels light up straight away, and fade out like the phosphor of a
+    /// CRT, losing half their brightness every ``half_life`` frames
+    Decay { half_life: f32 },
+
+    /// Show the average of the last ``frames`` frames
+    Blend { frames: usize },
+
+    /// Light pixels that were on in either of the last two frames
+    LastTwo,
+}
+
+impl FilterMode {
+    /// Parse a filter mode: ``none``, ``decay``, ``decay:<half life>``,
+    /// ``blend``, ``blend:<frames>`` or ``last2``
+    pub fn parse(spec: &str) -> Result<FilterMode, String> {
+        let mut parts = spec.splitn(2, ':');
+        let name = parts.next().unwrap_or("");
+        let argument = parts.next();
+
+        match (name, argument) {
+            ("none", None) => Ok(FilterMode::None),
+            ("last2", None) => Ok(FilterMode::LastTwo),
+            ("decay", None) => Ok(FilterMode::Decay { half_life: 2.0 }),
+            ("decay", Some(half_life)) => match half_life.parse::<f32>() {
+                Ok(half_life) if half_life > 0.0 => Ok(FilterMode::Decay { half_life }),
+                _ => Err(format!("'{}' is not a half life in frames", half_life)),
+            },
+            ("blend", None) => Ok(FilterMode::Blend { frames: 3 }),
+            ("blend", Some(frames)) => match frames.parse::<usize>() {
+                Ok(frames) if frames > 0 => Ok(FilterMode::Blend { frames }),
+                _ => Err(format!("'{}' is not a number of frames", frames)),
+            },
+            _ => Err(format!("unknown filter '{}', expected none, decay[:<half life>], blend[:<frames>] or last2", spec)),
+        }
+    }
+}
+
+/// Combines the frames shown on the display according to a ``FilterMode``
+#[derive(Debug, Clone)]
+pub struct Filter {
+    mode: FilterMode,
+
+    /// The size of the frames fed in so far
+    width: usize,
+    height: usize,
+
+    /// The brightness of each bitplane of each pixel in the last output
+    levels: Vec<[f32; 2]>,
+
+    /// The last frames fed in, newest first, for the modes that need them
+    history: VecDeque<Vec<u8>>,
+}
+
+impl Filter {
+    pub fn new(mode: FilterMode) -> Filter {
+        Filter {
+            mode,
+            width: 0,
+            height: 0,
+            levels: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn mode(&self) -> FilterMode {
+        self.mode
+    }
+
+    /// Change the filter mode, forgetting any earlier frames
+    pub fn set_mode(&mut self, mode: FilterMode) {
+        self.mode = mode;
+        self.history.clear();
+        self.levels.clear();
+    }
+
+    /// Feed in the next frame, given as one pixel value (0 - 3) per pixel
+    /// row by row, and get the brightness of both bitplanes of every pixel.
+    ///
+    /// If the size of the frame changes, earlier frames are forgotten.
+    pub fn apply(&mut self, width: usize, height: usize, pixels: &[u8]) -> &[[f32; 2]] {
+        assert_eq!(pixels.len(), width * height);
+        if (width, height) != (self.width, self.height) || self.levels.len() != pixels.len() {
+            self.width = width;
+            self.height = height;
+            self.levels = vec![[0.0; 2]; pixels.len()];
+            self.history.clear();
+        }
+
+        match self.mode {
+            FilterMode::None => {
+                for (level, &pixel) in self.levels.iter_mut().zip(pixels) {
+                    *level = planes(pixel);
+                }
+            },
+
+            FilterMode::Decay { half_life } => {
+                let decay = 0.5f32.powf(1.0 / half_life);
+                for (level, &pixel) in self.levels.iter_mut().zip(pixels) {
+                    let lit = planes(pixel);
+                    for plane in 0 .. 2 {
+                        level[plane] = lit[plane].max(level[plane] * decay);
+                    }
+                }
+            },
+
+            FilterMode::Blend { frames } => {
+                self.history.push_front(pixels.to_vec());
+                self.history.truncate(frames);
+
+                let weight = 1.0 / self.history.len() as f32;
+                for (index, level) in self.levels.iter_mut().enumerate() {
+                    *level = [0.0; 2];
+                    for frame in self.history.iter() {
+                        let lit = planes(frame[index]);
+                        level[0] += lit[0] * weight;
+                        level[1] += lit[1] * weight;
+                    }
+                }
+            },
+
+            FilterMode::LastTwo => {
+                self.history.push_front(pixels.to_vec());
+                self.history.truncate(2);
+
+                let previous = self.history.get(1).unwrap_or(&self.history[0]);
+                for ((level, &pixel), &last) in self.levels.iter_mut().zip(pixels).zip(previous) {
+                    *level = planes(pixel | last);
+                }
+            },
+        }
+
+        &self.levels
+    }
+}
+
+/// Split a pixel value into whether each bitplane is lit
+fn planes(pixel: u8) -> [f32; 2] {
+    [(pixel & 0x1) as f32, ((pixel >> 1) & 0x1) as f32]
+}
+
+/// Get the colour of a pixel from the brightness of its bitplanes, blending
+/// between the four colours of the palette.
+pub fn mix(palette: &Palette, level: [f32; 2]) -> Rgb {
+    let [first, second] = level;
+    let weights = [
+        (1.0 - first) * (1.0 - second),
+        first * (1.0 - second),
+        (1.0 - first) * second,
+        first * second,
+    ];
+
+    let mut color = [0.0f32; 3];
+    for (weight, palette_color) in weights.iter().zip(palette.colors.iter()) {
+        for channel in 0 .. 3 {
+            color[channel] += weight * palette_color[channel] as f32;
+        }
+    }
+    [color[0].round() as u8, color[1].round() as u8, color[2].round() as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed a 1 x 1 frame and get the brightness of its first bitplane
+    fn feed(filter: &mut Filter, pixel: u8) -> f32 {
+        filter.apply(1, 1, &[pixel])[0][0]
+    }
+
+    #[test]
+    fn none_shows_the_frame_as_it_is() {
+        let mut filter = Filter::new(FilterMode::None);
+        assert_eq!(filter.apply(2, 1, &[1, 2]), &[[1.0, 0.0], [0.0, 1.0]]);
+        assert_eq!(filter.apply(2, 1, &[0, 3]), &[[0.0, 0.0], [1.0, 1.0]]);
+    }
+
+    #[test]
+    fn decay_halves_after_the_half_life() {
+        let mut filter = Filter::new(FilterMode::Decay { half_life: 4.0 });
+        assert_eq!(feed(&mut filter, 1), 1.0);
+        let mut level = 0.0;
+        for _ in 0 .. 4 {
+            level = feed(&mut filter, 0);
+        }
+        assert!((level - 0.5).abs() < 1e-5, "{}", level);
+
+        // lit pixels jump straight back to full brightness
+        assert_eq!(feed(&mut filter, 1), 1.0);
+    }
+
+    #[test]
+    fn blend_averages_the_last_frames() {
+        let mut filter = Filter::new(FilterMode::Blend { frames: 4 });
+        feed(&mut filter, 1);
+        feed(&mut filter, 0);
+        feed(&mut filter, 1);
+        assert!((feed(&mut filter, 0) - 0.5).abs() < 1e-5);
+
+        // the first frame has dropped out of the window
+        assert!((feed(&mut filter, 0) - 0.25).abs() < 1e-5);
+    }
+
+    #[test]
+    fn last_two_ors_in_the_previous_frame() {
+        let mut filter = Filter::new(FilterMode::LastTwo);
+        assert_eq!(filter.apply(2, 1, &[1, 0]), &[[1.0, 0.0], [0.0, 0.0]]);
+        assert_eq!(filter.apply(2, 1, &[0, 2]), &[[1.0, 0.0], [0.0, 1.0]]);
+        assert_eq!(filter.apply(2, 1, &[0, 0]), &[[0.0, 0.0], [0.0, 1.0]]);
+    }
+
+    #[test]
+    fn a_new_size_forgets_earlier_frames() {
+        let mut filter = Filter::new(FilterMode::LastTwo);
+        filter.apply(1, 1, &[1]);
+        assert_eq!(filter.apply(2, 1, &[0, 0]), &[[0.0, 0.0], [0.0, 0.0]]);
+
+        let mut filter = Filter::new(FilterMode::Decay { half_life: 2.0 });
+        filter.apply(2, 1, &[1, 1]);
+        assert_eq!(filter.apply(1, 2, &[0, 0]), &[[0.0, 0.0], [0.0, 0.0]]);
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(FilterMode::parse("none"), Ok(FilterMode::None));
+        assert_eq!(FilterMode::parse("decay:1.5"), Ok(FilterMode::Decay { half_life: 1.5 }));
+        assert_eq!(FilterMode::parse("blend"), Ok(FilterMode::Blend { frames: 3 }));
+        assert_eq!(FilterMode::parse("last2"), Ok(FilterMode::LastTwo));
+        assert!(FilterMode::parse("decay:0").is_err());
+        assert!(FilterMode::parse("blend:0").is_err());
+        assert!(FilterMode::parse("sharpen").is_err());
+    }
+}
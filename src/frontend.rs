@@ -0,0 +1,198 @@
+//! Traits for the video, audio and input backends of a frontend, and a
+//! ``Machine`` that runs a Chip8 through them.
+//!
+//! A frontend only has to implement ``Display``, ``Audio`` and ``Input`` for
+//! its platform. ``Machine`` takes care of pacing the emulation at 60 frames
+//! per second, running the instructions of each frame, and pushing the
+//! display, sound and keypad state in and out of the backends.
+
+use std::thread;
+use std::time::{ Duration, Instant };
+
+use debugger::{ Debugger, StopReason };
+use Chip8;
+
+/// Shows the Chip8 display on the host
+pub trait Display {
+    /// Draw the current contents of the display. Called whenever they have
+    /// changed.
+    fn draw(&mut self, chip: &Chip8);
+
+    /// Show what was last drawn on the host. Called once at the end of every
+    /// frame, for backends that draw into a buffer first.
+    fn present(&mut self) {}
+}
+
+/// Plays the Chip8 buzzer on the host
+pub trait Audio {
+    /// Start or stop the sound. ``chip`` gives access to the XO-CHIP audio
+    /// pattern and pitch for backends that play them.
+    ///
+    /// Called once at the end of every frame, including the frames where
+    /// nothing changed, so backends can generate the sound a frame at a
+    /// time with ``audio::Synth``.
+    fn set_playing(&mut self, playing: bool, chip: &Chip8);
+}
+
+/// Reads the Chip8 keypad from the host
+pub trait Input {
+    /// Process pending host input and update the state of the keypad.
+    /// Returns false once the user has asked to quit.
+    fn poll(&mut self, chip: &mut Chip8) -> bool;
+}
+
+/// Runs a Chip8 in real time through a set of frontend backends.
+///
+/// Execution goes through a ``Debugger``, so breakpoints set on it stop
+/// ``run_frame()`` and ``run()`` part of the way through a frame.
+pub struct Machine<D: Display, A: Audio, I: Input> {
+    chip: Chip8,
+    debugger: Debugger,
+    display: D,
+    audio: A,
+    input: I,
+
+    /// The number of instructions executed in each frame
+    cycles_per_frame: usize,
+
+    /// The time the current frame is due to start
+    next_frame: Instant,
+}
+
+/// The length of a 60Hz frame
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+impl<D: Display, A: Audio, I: Input> Machine<D, A, I> {
+    /// Create a machine that executes ``cycles_per_frame`` instructions in
+    /// each frame
+    pub fn new(chip: Chip8, display: D, audio: A, input: I, cycles_per_frame: usize) -> Machine<D, A, I> {
+        Machine {
+            chip,
+            debugger: Debugger::new(),
+            display,
+            audio,
+            input,
+            cycles_per_frame,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Get the emulated Chip8
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    /// Get the emulated Chip8, to change its state
+    pub fn chip_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip
+    }
+
+    /// Get the debugger that controls execution
+    pub fn debugger(&self) -> &Debugger {
+        &self.debugger
+    }
+
+    /// Get the emulated Chip8 and the debugger that controls it together,
+    /// as debugger commands need both
+    pub fn chip_and_debugger_mut(&mut self) -> (&mut Chip8, &mut Debugger) {
+        (&mut self.chip, &mut self.debugger)
+    }
+
+    /// Get the display backend
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    /// Get the display backend, to change its state
+    pub fn display_mut(&mut self) -> &mut D {
+        &mut self.display
+    }
+
+    /// Get the audio backend
+    pub fn audio(&self) -> &A {
+        &self.audio
+    }
+
+    /// Get the audio backend, to change its state
+    pub fn audio_mut(&mut self) -> &mut A {
+        &mut self.audio
+    }
+
+    /// Get the input backend
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    /// Get the input backend, to change its state
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Update the keypad from the input backend. Returns false once the
+    /// user has asked to quit.
+    pub fn poll_input(&mut self) -> bool {
+        self.input.poll(&mut self.chip)
+    }
+
+    /// Sleep until it is time for the next frame.
+    ///
+    /// If the host has fallen more than a few frames behind, it doesn't try
+    /// to catch up.
+    pub fn wait_for_frame(&mut self) {
+        let now = Instant::now();
+        if now < self.next_frame {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > FRAME_DURATION * 4 {
+            self.next_frame = now;
+        }
+        self.next_frame += FRAME_DURATION;
+    }
+
+    /// Start pacing frames from now, after the emulation has been paused
+    pub fn reset_clock(&mut self) {
+        self.next_frame = Instant::now();
+    }
+
+    /// Run one frame of instructions, then show the display if it changed
+    /// and update the sound.
+    ///
+    /// If the program stops part of the way through the frame, the reason
+    /// is returned and the next call carries on from there. When it stops
+    /// because of an emulation error the display and sound are still
+    /// updated, since the frontend usually carries on straight away.
+    pub fn run_frame(&mut self) -> Result<(), StopReason> {
+        let result = self.debugger.run_frame(&mut self.chip, self.cycles_per_frame);
+        match result {
+            Ok(true) | Err(StopReason::Error(_)) => self.display.draw(&self.chip),
+            Ok(false) => (),
+            Err(reason) => return Err(reason),
+        }
+        self.audio.set_playing(self.chip.sound_status(), &self.chip);
+        self.display.present();
+        result.map(|_| ())
+    }
+
+    /// Draw and present the display, whether or not it changed
+    pub fn draw(&mut self) {
+        self.display.draw(&self.chip);
+        self.display.present();
+    }
+
+    /// Stop the sound, whatever the state of the sound timer
+    pub fn silence(&mut self) {
+        self.audio.set_playing(false, &self.chip);
+    }
+
+    /// Run in real time until the user quits or the program exits.
+    ///
+    /// If the program stops, the reason is returned. Calling ``run()`` again
+    /// carries on, once the debugger has been told to resume if it was a
+    /// breakpoint that stopped it.
+    pub fn run(&mut self) -> Result<(), StopReason> {
+        while self.poll_input() && !self.chip.has_exited() {
+            self.wait_for_frame();
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+}
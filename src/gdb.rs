@@ -0,0 +1,533 @@
+//! A GDB remote serial protocol server for the Chip8.
+//!
+//! This lets standard debuggers attach to a running ROM over TCP. The
+//! registers are exposed in the order V0 - VF, I, PC, SP, DT, ST, with the
+//! 16-bit ones in little endian byte order, and described to the client by
+//! a target description so it can show them by name. Memory can be read and
+//! written, and software breakpoints, single stepping and continuing are
+//! handled by a ``Debugger``, which executes the program with ``tick()``.
+
+use std::io::{ self, Read, Write };
+use std::net::TcpStream;
+
+use debugger::{ Debugger, StopReason };
+use { Chip8, EmulationError };
+
+/// The number of registers reported to the client
+const REGISTER_COUNT: usize = 21;
+
+/// Describes the registers to the client
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// The signals reported to the client when the program stops
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// Serves a single debugging session over a TCP connection.
+pub struct GdbServer {
+    chip: Chip8,
+    debugger: Debugger,
+
+    /// The number of instructions executed between timer ticks
+    cycles_per_frame: usize,
+}
+
+/// What to do after handling a packet
+enum Action {
+    /// Send the reply and wait for the next packet
+    Reply(String),
+
+    /// Acknowledge the packet and run until the program stops
+    Resume,
+
+    /// End the session, sending the reply first if there is one
+    Close(Option<String>),
+}
+
+impl GdbServer {
+    /// Create a server for the given machine. The timers tick after every
+    /// ``cycles_per_frame`` instructions.
+    pub fn new(chip: Chip8, cycles_per_frame: usize) -> GdbServer {
+        GdbServer {
+            chip,
+            debugger: Debugger::new(),
+            cycles_per_frame,
+        }
+    }
+
+    /// Get the machine being debugged
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    /// Talk to a connected client until it detaches, kills the program or
+    /// disconnects.
+    ///
+    /// The program starts out stopped. While it runs, it runs as fast as the
+    /// host allows, and the client can interrupt it at any time.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        let mut connection = Connection { stream, buffer: Vec::new(), ack: true };
+
+        while let Some(packet) = connection.read_packet()? {
+            let action = self.handle(&packet, &mut connection.ack);
+            match action {
+                Action::Reply(reply) => connection.write_packet(&reply)?,
+                Action::Resume => {
+                    let reply = self.run(&mut connection)?;
+                    connection.write_packet(&reply)?;
+                    if self.chip.has_exited() {
+                        break;
+                    }
+                },
+                Action::Close(reply) => {
+                    if let Some(reply) = reply {
+                        connection.write_packet(&reply)?;
+                    }
+                    break;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the program until it stops, returning the stop reply
+    fn run(&mut self, connection: &mut Connection) -> io::Result<String> {
+        loop {
+            match self.debugger.run_frame(&mut self.chip, self.cycles_per_frame) {
+                Ok(_) => {
+                    if self.chip.has_exited() {
+                        return Ok("W00".to_string());
+                    }
+                    if connection.interrupted()? {
+                        return Ok(stop_reply(SIGINT));
+                    }
+                },
+                Err(StopReason::Error(EmulationError::UnknownOpcode { .. })) =>
+                    return Ok(stop_reply(SIGILL)),
+                Err(StopReason::Error(_)) =>
+                    return Ok(stop_reply(SIGSEGV)),
+                Err(_) =>
+                    return Ok(stop_reply(SIGTRAP)),
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &[u8], ack: &mut bool) -> Action {
+        let packet = String::from_utf8_lossy(packet);
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+
+        match command {
+            "?" => Action::Reply(stop_reply(SIGTRAP)),
+
+            "g" => Action::Reply((0 .. REGISTER_COUNT).map(|register| self.read_register(register)).collect()),
+            "G" => {
+                let mut args = args;
+                for register in 0 .. REGISTER_COUNT {
+                    let size = register_size(register) * 2;
+                    if args.len() < size {
+                        return Action::Reply("E01".to_string());
+                    }
+                    let (value, rest) = args.split_at(size);
+                    if register != 18 && !self.write_register(register, value) {
+                        return Action::Reply("E01".to_string());
+                    }
+                    args = rest;
+                }
+                Action::Reply("OK".to_string())
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < REGISTER_COUNT => Action::Reply(self.read_register(register)),
+                _ => Action::Reply("E01".to_string()),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let register = parts.next().and_then(|register| usize::from_str_radix(register, 16).ok());
+                match (register, parts.next()) {
+                    (Some(register), Some(value)) if self.write_register(register, value) =>
+                        Action::Reply("OK".to_string()),
+                    _ => Action::Reply("E01".to_string()),
+                }
+            },
+
+            "m" => match parse_range(args) {
+                Some((address, len)) if address < self.chip.get_mem().len() => {
+                    let mem = self.chip.get_mem();
+                    let end = std::cmp::min(address.saturating_add(len), mem.len());
+                    Action::Reply(encode_hex(&mem[address .. end]))
+                },
+                _ => Action::Reply("E01".to_string()),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let range = parts.next().and_then(parse_range);
+                let data = parts.next().and_then(decode_hex);
+                match (range, data) {
+                    (Some((address, len)), Some(ref data))
+                        if data.len() == len
+                            && address.checked_add(len).is_some_and(|end| end <= self.chip.get_mem().len()) =>
+                    {
+                        self.chip.set_mem(address, data);
+                        Action::Reply("OK".to_string())
+                    },
+                    _ => Action::Reply("E01".to_string()),
+                }
+            },
+
+            "Z" | "z" => {
+                let mut parts = args.split(',');
+                let kind = parts.next();
+                let address = parts.next().and_then(|address| u16::from_str_radix(address, 16).ok());
+                match (kind, address) {
+                    // software and hardware breakpoints are the same thing here
+                    (Some("0"), Some(address)) | (Some("1"), Some(address)) => {
+                        if command == "Z" {
+                            self.debugger.add_breakpoint(address);
+                        } else {
+                            self.debugger.remove_breakpoint(address);
+                        }
+                        Action::Reply("OK".to_string())
+                    },
+                    (Some(_), Some(_)) => Action::Reply(String::new()),
+                    _ => Action::Reply("E01".to_string()),
+                }
+            },
+
+            "c" | "s" => {
+                if !args.is_empty() {
+                    match u16::from_str_radix(args, 16) {
+                        Ok(address) => self.chip.set_pc(address),
+                        Err(_) => return Action::Reply("E01".to_string()),
+                    }
+                }
+                if command == "c" {
+                    self.debugger.resume();
+                } else {
+                    self.debugger.step();
+                }
+                Action::Resume
+            },
+
+            "H" => Action::Reply("OK".to_string()),
+            "D" => Action::Close(Some("OK".to_string())),
+            "k" => Action::Close(None),
+
+            _ if packet == "QStartNoAckMode" => {
+                *ack = false;
+                Action::Reply("OK".to_string())
+            },
+            _ if packet.starts_with("qSupported") =>
+                Action::Reply("PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string()),
+            _ if packet.starts_with("qXfer:features:read:target.xml:") => {
+                let range = &packet["qXfer:features:read:target.xml:".len() ..];
+                match parse_range(range) {
+                    Some((offset, len)) => {
+                        let start = std::cmp::min(offset, TARGET_XML.len());
+                        let end = std::cmp::min(start.saturating_add(len), TARGET_XML.len());
+                        let more = if end < TARGET_XML.len() { "m" } else { "l" };
+                        Action::Reply(format!("{}{}", more, &TARGET_XML[start .. end]))
+                    },
+                    None => Action::Reply("E01".to_string()),
+                }
+            },
+            _ if packet == "qAttached" => Action::Reply("1".to_string()),
+            _ if packet == "qC" => Action::Reply("QC1".to_string()),
+            _ if packet == "qfThreadInfo" => Action::Reply("m1".to_string()),
+            _ if packet == "qsThreadInfo" => Action::Reply("l".to_string()),
+
+            // an empty reply tells the client the packet is not supported
+            _ => Action::Reply(String::new()),
+        }
+    }
+
+    /// Encode the value of a register as hex digits in target byte order
+    fn read_register(&self, register: usize) -> String {
+        let value = match register {
+            0 ..= 15 => self.chip.get_v(register) as u16,
+            16 => self.chip.get_i(),
+            17 => self.chip.get_pc(),
+            18 => self.chip.get_sp() as u16,
+            19 => self.chip.get_dt() as u16,
+            20 => self.chip.get_st() as u16,
+            _ => 0,
+        };
+        encode_hex(&[value as u8, (value >> 8) as u8][.. register_size(register)])
+    }
+
+    /// Set a register from hex digits in target byte order, returning false
+    /// if the value is malformed or the register can't be written
+    fn write_register(&mut self, register: usize, value: &str) -> bool {
+        let bytes = match decode_hex(value) {
+            Some(ref bytes) if register < REGISTER_COUNT && bytes.len() == register_size(register) =>
+                bytes.clone(),
+            _ => return false,
+        };
+        let value = bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u16);
+
+        match register {
+            0 ..= 15 => self.chip.set_v(register, value as u8),
+            16 => self.chip.set_i(value),
+            17 => self.chip.set_pc(value),
+            19 => self.chip.set_dt(value as u8),
+            20 => self.chip.set_st(value as u8),
+            // the stack pointer is read-only
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// The size in bytes of a register
+fn register_size(register: usize) -> usize {
+    match register {
+        16 | 17 => 2,
+        _ => 1,
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+/// Parse an ``address,length`` pair of hex numbers
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let mut parts = args.splitn(2, ',');
+    let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+    let len = usize::from_str_radix(parts.next()?, 16).ok()?;
+    Some((address, len))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(digits: &str) -> Option<Vec<u8>> {
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0 .. digits.len()).step_by(2)
+        .map(|index| u8::from_str_radix(digits.get(index .. index + 2)?, 16).ok())
+        .collect()
+}
+
+/// Frames packets on the connection to the client
+struct Connection {
+    stream: TcpStream,
+
+    /// Bytes received but not yet handled
+    buffer: Vec<u8>,
+
+    /// Whether packets are acknowledged, until the client turns it off
+    ack: bool,
+}
+
+impl Connection {
+    /// Wait for the next packet, returning its data or ``None`` once the
+    /// client disconnects. Interrupts received while the program is stopped
+    /// are dropped.
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            if let Some(start) = self.buffer.iter().position(|&byte| byte == b'$') {
+                if let Some(end) = self.buffer[start ..].iter().position(|&byte| byte == b'#') {
+                    let end = start + end;
+                    if self.buffer.len() >= end + 3 {
+                        let data = self.buffer[start + 1 .. end].to_vec();
+                        let checksum = std::str::from_utf8(&self.buffer[end + 1 .. end + 3]).ok()
+                            .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                        self.buffer.drain(.. end + 3);
+
+                        if checksum == Some(checksum_of(&data)) {
+                            if self.ack {
+                                self.stream.write_all(b"+")?;
+                            }
+                            return Ok(Some(unescape(&data)));
+                        } else if self.ack {
+                            self.stream.write_all(b"-")?;
+                        }
+                        continue;
+                    }
+                }
+            } else {
+                // acknowledgements and stray interrupts
+                self.buffer.clear();
+            }
+
+            let mut chunk = [0; 1024];
+            let read = self.stream.read(&mut chunk)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&chunk[.. read]);
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.stream.write_all(packet.as_bytes())
+    }
+
+    /// Check, without waiting, whether the client has asked to interrupt
+    /// the running program
+    fn interrupted(&mut self) -> io::Result<bool> {
+        // the interrupt may have arrived along with the packet that
+        // resumed the program
+        if self.buffer.contains(&0x03) {
+            self.buffer.retain(|&byte| byte != 0x03);
+            return Ok(true);
+        }
+
+        self.stream.set_nonblocking(true)?;
+        let mut chunk = [0; 1024];
+        let result = self.stream.read(&mut chunk);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "client disconnected")),
+            Ok(read) => {
+                let interrupted = chunk[.. read].contains(&0x03);
+                self.buffer.extend(chunk[.. read].iter().filter(|&&byte| byte != 0x03));
+                Ok(interrupted)
+            },
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+/// Undo the escaping of ``#``, ``$``, ``}`` and ``*`` in packet data
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut bytes = data.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'}' {
+            if let Some(&escaped) = bytes.next() {
+                output.push(escaped ^ 0x20);
+            }
+        } else {
+            output.push(byte);
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{ TcpListener, TcpStream };
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use Quirks;
+
+    /// Send a packet and wait for the reply, checking that it was
+    /// acknowledged with ``ack`` and that the reply's checksum is right
+    fn exchange(stream: &mut TcpStream, packet: &str, ack: &str) -> String {
+        write!(stream, "${}#{:02x}", packet, checksum_of(packet.as_bytes())).unwrap();
+        read_reply(stream, ack)
+    }
+
+    fn read_reply(stream: &mut TcpStream, ack: &str) -> String {
+        let mut received = Vec::new();
+        let mut byte = [0];
+        while received.len() < 3 || received[received.len() - 3] != b'#' {
+            stream.read_exact(&mut byte).unwrap();
+            received.push(byte[0]);
+        }
+
+        let received = String::from_utf8(received).unwrap();
+        let start = received.find('$').unwrap();
+        assert_eq!(&received[.. start], ack);
+        let (data, checksum) = received[start + 1 ..].split_at(received.len() - start - 4);
+        assert_eq!(&checksum[1 ..], format!("{:02x}", checksum_of(data.as_bytes())));
+        data.to_string()
+    }
+
+    #[test]
+    fn serves_a_scripted_client() {
+        // ld v0, 5; loop: add v0, 1; jp loop
+        let chip = Chip8::with_rom_data(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02], Quirks::default()).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut server = GdbServer::new(chip, 15);
+            server.serve(stream).map(|_| server)
+        });
+
+        let mut client = TcpStream::connect(address).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        client.set_nodelay(true).unwrap();
+
+        // a bad checksum is refused, and the packet can be sent again
+        client.write_all(b"$?#00").unwrap();
+        let mut nack = [0];
+        client.read_exact(&mut nack).unwrap();
+        assert_eq!(&nack, b"-");
+
+        assert_eq!(exchange(&mut client, "?", "+"), "S05");
+        assert_eq!(exchange(&mut client, "g", "+"), format!("{}00000002000000", "00".repeat(16)));
+        assert_eq!(exchange(&mut client, "m200,4", "+"), "60057001");
+        assert_eq!(exchange(&mut client, "mffff,4", "+"), "00");
+        assert_eq!(exchange(&mut client, "mzz,4", "+"), "E01");
+
+        // writes must fit in memory
+        assert_eq!(exchange(&mut client, "Mffff,2:0102", "+"), "E01");
+        assert_eq!(exchange(&mut client, &format!("M{:x},1:01", usize::MAX), "+"), "E01");
+        assert_eq!(exchange(&mut client, "Mfffe,2:0102", "+"), "OK");
+        assert_eq!(exchange(&mut client, "mfffe,2", "+"), "0102");
+
+        assert_eq!(exchange(&mut client, "QStartNoAckMode", "+"), "OK");
+
+        // run to the breakpoint, then around the loop back to it
+        assert_eq!(exchange(&mut client, "Z0,202,2", ""), "OK");
+        assert_eq!(exchange(&mut client, "c", ""), "S05");
+        assert_eq!(exchange(&mut client, "p11", ""), "0202");
+        assert_eq!(exchange(&mut client, "c", ""), "S05");
+        assert_eq!(exchange(&mut client, "p0", ""), "06");
+        assert_eq!(exchange(&mut client, "s", ""), "S05");
+        assert_eq!(exchange(&mut client, "p11", ""), "0402");
+
+        // without the breakpoint it runs until interrupted
+        assert_eq!(exchange(&mut client, "z0,202,2", ""), "OK");
+        write!(client, "$c#{:02x}", checksum_of(b"c")).unwrap();
+        client.write_all(&[0x03]).unwrap();
+        assert_eq!(read_reply(&mut client, ""), "S02");
+
+        write!(client, "$k#{:02x}", checksum_of(b"k")).unwrap();
+        let server = server.join().unwrap().unwrap();
+        assert_eq!(server.chip().get_mem()[0xfffe], 0x01);
+    }
+}
@@ -0,0 +1,304 @@
+//! Runs a Chip8 without any window, sound or keyboard, for scripted and
+//! automated use.
+//!
+//! Key presses are fed in from a script of timed events, and once the run
+//! is over the display can be dumped as text or as a PNG image.
+
+use std::fs::File;
+use std::io::Read;
+
+use png;
+use { Chip8, EmulationError };
+
+/// A change to the state of a key at the start of an emulated frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame the change happens at, counting from 0
+    pub frame: u64,
+
+    /// The key on the keypad, 0 - F
+    pub key: u8,
+
+    /// Whether the key is pressed or released
+    pub pressed: bool,
+}
+
+/// Parse a key script.
+///
+/// Each line holds a frame number, a hexadecimal key and either ``down`` or
+/// ``up``, for example ``120 5 down``. Blank lines and anything after a ``#``
+/// are ignored.
+pub fn parse_script(source: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        if words.len() != 3 {
+            return Err(format!("line {}: expected '<frame> <key> down|up'", number + 1));
+        }
+
+        let frame = words[0].parse::<u64>()
+            .map_err(|_| format!("line {}: '{}' is not a frame number", number + 1, words[0]))?;
+        let key = match u8::from_str_radix(words[1], 16) {
+            Ok(key) if key < 0x10 => key,
+            _ => return Err(format!("line {}: '{}' is not a key 0 - F", number + 1, words[1])),
+        };
+        let pressed = match words[2] {
+            "down" => true,
+            "up" => false,
+            other => return Err(format!("line {}: expected down or up, found '{}'", number + 1, other)),
+        };
+
+        events.push(KeyEvent { frame, key, pressed });
+    }
+
+    Ok(events)
+}
+
+/// Read and parse a key script from a file
+pub fn load_script(path: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("{}: {}", path, err))?;
+    parse_script(&source).map_err(|err| format!("{}:{}", path, err))
+}
+
+/// Drives a Chip8 in 60Hz frames, without waiting for real time to pass.
+pub struct Headless {
+    chip: Chip8,
+
+    /// The number of instructions executed in each frame
+    cycles_per_frame: usize,
+
+    /// Key events in the order they happen
+    script: Vec<KeyEvent>,
+
+    /// The index of the first event in ``script`` that hasn't happened yet
+    next_event: usize,
+
+    /// The number of frames completed so far
+    frame: u64,
+
+    /// The number of instructions already executed in the current frame
+    executed: usize,
+}
+
+impl Headless {
+    /// Create a runner for the given machine, executing ``cycles_per_frame``
+    /// instructions in every frame
+    pub fn new(chip: Chip8, cycles_per_frame: usize) -> Headless {
+        Headless {
+            chip,
+            cycles_per_frame,
+            script: Vec::new(),
+            next_event: 0,
+            frame: 0,
+            executed: 0,
+        }
+    }
+
+    /// Queue up key events. Events for frames that have already started are
+    /// applied at the start of the next one.
+    pub fn add_key_events(&mut self, events: &[KeyEvent]) {
+        self.script.extend_from_slice(events);
+        // the sort is stable, so events for the same frame keep their order
+        self.script[self.next_event ..].sort_by_key(|event| event.frame);
+    }
+
+    /// Get the machine being run
+    pub fn chip(&self) -> &Chip8 {
+        &self.chip
+    }
+
+    /// Get the machine being run, to change its state
+    pub fn chip_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip
+    }
+
+    /// Get the number of frames completed so far
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Run ``frames`` complete frames, stopping early if the program exits.
+    ///
+    /// If an instruction fails, the run stops there and the error is
+    /// returned.
+    pub fn run_frames(&mut self, frames: u64) -> Result<(), EmulationError> {
+        let end = self.frame + frames;
+        while self.frame < end && !self.chip.has_exited() {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Run ``instructions`` instructions, stopping early if the program
+    /// exits. The timers tick at the end of every frame along the way.
+    ///
+    /// If an instruction fails, the run stops there and the error is
+    /// returned.
+    pub fn run_instructions(&mut self, instructions: u64) -> Result<(), EmulationError> {
+        for _ in 0 .. instructions {
+            if self.chip.has_exited() {
+                break;
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction, applying the key events due at the
+    /// start of the frame and ticking the timers at the end of it
+    fn step(&mut self) -> Result<(), EmulationError> {
+        if self.executed == 0 {
+            while let Some(event) = self.script.get(self.next_event) {
+                if event.frame > self.frame {
+                    break;
+                }
+                if event.pressed {
+                    self.chip.key_down(event.key as usize);
+                } else {
+                    self.chip.key_up(event.key as usize);
+                }
+                self.next_event += 1;
+            }
+        }
+
+        let result = self.chip.tick();
+        self.executed += 1;
+        if self.executed >= self.cycles_per_frame {
+            self.chip.tick_timers();
+            self.executed = 0;
+            self.frame += 1;
+        }
+        result
+    }
+
+    /// Draw the display as text, one line per row. Pixels that are off are
+    /// shown as ``.``, and pixels that are on as ``#``, or as ``+`` and ``@``
+    /// for the second and both XO-CHIP bitplanes.
+    pub fn framebuffer_ascii(&self) -> String {
+        let (width, height) = (self.chip.display_width(), self.chip.display_height());
+        let mut text = String::with_capacity((width + 1) * height);
+        for y in 0 .. height {
+            for x in 0 .. width {
+                text.push(['.', '#', '+', '@'][self.chip.get_pixel_color(x, y) as usize & 0x3]);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Encode the display as a greyscale PNG image, with every pixel drawn
+    /// as a ``scale`` x ``scale`` square
+    pub fn framebuffer_png(&self, scale: usize) -> Vec<u8> {
+        let scale = scale.max(1);
+        let (width, height) = (self.chip.display_width(), self.chip.display_height());
+        let shades = [0x00, 0xff, 0xaa, 0x55];
+
+        let mut pixels = Vec::with_capacity(width * height * scale * scale);
+        for y in 0 .. height * scale {
+            for x in 0 .. width * scale {
+                pixels.push(shades[self.chip.get_pixel_color(x / scale, y / scale) as usize & 0x3]);
+            }
+        }
+        png::encode_greyscale(width * scale, height * scale, &pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use { asm, Quirks };
+
+    fn runner(source: &str, cycles_per_frame: usize) -> Headless {
+        let rom = asm::assemble(source).unwrap();
+        Headless::new(Chip8::with_rom_data(&rom, Quirks::default()).unwrap(), cycles_per_frame)
+    }
+
+    #[test]
+    fn parses_key_scripts() {
+        let script = "# press 5 for a second\n\n  0 5 down\n60 5 up  # let go\n61 a down\n62 F up\n";
+        assert_eq!(parse_script(script), Ok(vec![
+            KeyEvent { frame: 0, key: 0x5, pressed: true },
+            KeyEvent { frame: 60, key: 0x5, pressed: false },
+            KeyEvent { frame: 61, key: 0xa, pressed: true },
+            KeyEvent { frame: 62, key: 0xf, pressed: false },
+        ]));
+        assert_eq!(parse_script("# nothing here\n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_bad_key_scripts() {
+        assert_eq!(parse_script("0 5 down\n1 5"), Err("line 2: expected '<frame> <key> down|up'".to_string()));
+        assert_eq!(parse_script("0 5 down now"), Err("line 1: expected '<frame> <key> down|up'".to_string()));
+        assert_eq!(parse_script("-1 5 down"), Err("line 1: '-1' is not a frame number".to_string()));
+        assert_eq!(parse_script("0 10 down"), Err("line 1: '10' is not a key 0 - F".to_string()));
+        assert_eq!(parse_script("0 g down"), Err("line 1: 'g' is not a key 0 - F".to_string()));
+        assert_eq!(parse_script("0 5 pressed"), Err("line 1: expected down or up, found 'pressed'".to_string()));
+    }
+
+    #[test]
+    fn key_events_happen_at_the_start_of_their_frame() {
+        let mut runner = runner("loop: JP loop", 4);
+        runner.add_key_events(&parse_script("3 5 down\n5 5 up\n3 7 down").unwrap());
+
+        runner.run_frames(3).unwrap();
+        assert!(!runner.chip().get_input(0x5));
+        runner.run_instructions(1).unwrap();
+        assert!(runner.chip().get_input(0x5) && runner.chip().get_input(0x7));
+
+        // the release lands on frame 5, even when it starts partway into a run
+        runner.run_instructions(4).unwrap();
+        assert!(runner.chip().get_input(0x5));
+        runner.run_instructions(3).unwrap();
+        assert_eq!(runner.frame(), 5);
+        assert!(runner.chip().get_input(0x5));
+        runner.run_instructions(1).unwrap();
+        assert!(!runner.chip().get_input(0x5) && runner.chip().get_input(0x7));
+
+        // events added late are applied at the start of the next frame
+        runner.add_key_events(&[KeyEvent { frame: 0, key: 0x7, pressed: false }]);
+        runner.run_frames(1).unwrap();
+        assert!(runner.chip().get_input(0x7));
+        runner.run_instructions(1).unwrap();
+        assert!(!runner.chip().get_input(0x7));
+    }
+
+    #[test]
+    fn runs_are_deterministic_for_a_seed() {
+        let source = "loop: RND V0, #FF \n RND V1, #0F \n LD I, #300 \n LD [I], V1 \n JP loop";
+        let run = |seed| {
+            let mut runner = runner(source, 15);
+            runner.chip_mut().seed_rng(seed);
+            runner.add_key_events(&parse_script("2 1 down\n4 1 up").unwrap());
+            runner.run_frames(10).unwrap();
+            runner.chip().save_state()
+        };
+        assert_eq!(run(42), run(42));
+        assert!(run(42) != run(43));
+    }
+
+    #[test]
+    fn dumps_the_display_as_text() {
+        let mut runner = runner("LD V0, 1 \n LD F, V0 \n LD V0, 2 \n DRW V0, V0, 5 \n done: JP done", 15);
+        runner.run_frames(1).unwrap();
+
+        let ascii = runner.framebuffer_ascii();
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert!(lines.iter().all(|line| line.len() == 64));
+        assert_eq!(&lines[1][.. 8], "........");
+        assert_eq!(&lines[2][.. 8], "....#...");
+        assert_eq!(&lines[3][.. 8], "...##...");
+        assert_eq!(&lines[4][.. 8], "....#...");
+        assert_eq!(&lines[5][.. 8], "....#...");
+        assert_eq!(&lines[6][.. 8], "...###..");
+        assert_eq!(ascii.matches('#').count(), 8);
+    }
+}
@@ -0,0 +1,299 @@
+/// A decoded Chip8 instruction, covering the base instruction set as well
+/// as the SUPER-CHIP and XO-CHIP extensions.
+///
+/// Register operands are indices 0 - F, ``byte`` operands are the low 8 bits
+/// of the opcode, and addresses are the low 12 bits (16 bits for the XO-CHIP
+/// long load).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn calls a machine code routine on the host (unsupported)
+    Sys(u16),
+    /// 00cn scrolls the display down by n pixels
+    ScrollDown(u8),
+    /// 00dn scrolls the display up by n pixels
+    ScrollUp(u8),
+    /// 00e0 clears the display
+    Clear,
+    /// 00ee returns from a subroutine
+    Return,
+    /// 00fb scrolls the display right by 4 pixels
+    ScrollRight,
+    /// 00fc scrolls the display left by 4 pixels
+    ScrollLeft,
+    /// 00fd exits the interpreter
+    Exit,
+    /// 00fe switches to low resolution mode
+    Lores,
+    /// 00ff switches to high resolution mode
+    Hires,
+    /// 1nnn jumps to location nnn
+    Jump(u16),
+    /// 2nnn calls the subroutine at nnn
+    Call(u16),
+    /// 3xkk skips the next instruction if Vx == kk
+    SkipEqualByte(u8, u8),
+    /// 4xkk skips the next instruction if Vx != kk
+    SkipNotEqualByte(u8, u8),
+    /// 5xy0 skips the next instruction if Vx == Vy
+    SkipEqual(u8, u8),
+    /// 5xy2 stores registers Vx - Vy into ram at location i
+    StoreRange(u8, u8),
+    /// 5xy3 fills registers Vx - Vy from ram at location i
+    LoadRange(u8, u8),
+    /// 6xkk sets Vx = kk
+    LoadByte(u8, u8),
+    /// 7xkk sets Vx = Vx + kk
+    AddByte(u8, u8),
+    /// 8xy0 sets Vx = Vy
+    Move(u8, u8),
+    /// 8xy1 sets Vx = Vx OR Vy
+    Or(u8, u8),
+    /// 8xy2 sets Vx = Vx AND Vy
+    And(u8, u8),
+    /// 8xy3 sets Vx = Vx XOR Vy
+    Xor(u8, u8),
+    /// 8xy4 sets Vx = Vx + Vy, with carry in Vf
+    Add(u8, u8),
+    /// 8xy5 sets Vx = Vx - Vy, with not borrow in Vf
+    Sub(u8, u8),
+    /// 8xy6 shifts right by one
+    ShiftRight(u8, u8),
+    /// 8xy7 sets Vx = Vy - Vx, with not borrow in Vf
+    SubReverse(u8, u8),
+    /// 8xye shifts left by one
+    ShiftLeft(u8, u8),
+    /// 9xy0 skips the next instruction if Vx != Vy
+    SkipNotEqual(u8, u8),
+    /// annn sets i to nnn
+    LoadI(u16),
+    /// bnnn jumps to nnn + V0
+    JumpOffset(u16),
+    /// cxkk sets Vx to a random byte ANDed with kk
+    Random(u8, u8),
+    /// dxyn draws a sprite of height n at (Vx, Vy)
+    Draw(u8, u8, u8),
+    /// ex9e skips the next instruction if the key Vx is pressed
+    SkipKey(u8),
+    /// exa1 skips the next instruction if the key Vx is not pressed
+    SkipNotKey(u8),
+    /// f000 nnnn sets i to the 16-bit address nnnn
+    LoadILong(u16),
+    /// fn01 selects the bitplanes n to draw on
+    Plane(u8),
+    /// f002 loads the audio pattern from ram at location i
+    Audio,
+    /// fx07 sets Vx to the value of the delay timer
+    GetDelay(u8),
+    /// fx0a waits for a key press and stores it in Vx
+    WaitKey(u8),
+    /// fx15 sets the delay timer to Vx
+    SetDelay(u8),
+    /// fx18 sets the sound timer to Vx
+    SetSound(u8),
+    /// fx1e adds Vx to i
+    AddI(u8),
+    /// fx29 sets i to the small font sprite of digit Vx
+    Font(u8),
+    /// fx30 sets i to the large font sprite of digit Vx
+    BigFont(u8),
+    /// fx33 stores the BCD representation of Vx at i
+    Bcd(u8),
+    /// fx3a sets the audio pattern pitch to Vx
+    Pitch(u8),
+    /// fx55 stores registers V0 - Vx into ram at location i
+    Store(u8),
+    /// fx65 fills registers V0 - Vx from ram at location i
+    Load(u8),
+    /// fx75 stores registers V0 - Vx in the RPL user flags
+    StoreFlags(u8),
+    /// fx85 fills registers V0 - Vx from the RPL user flags
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of ``bytes``.
+    ///
+    /// Returns ``None`` if the bytes don't hold a valid instruction, or if
+    /// there are too few of them for the instruction's length.
+    pub fn decode(bytes: &[u8]) -> Option<Instruction> {
+        if bytes.len() < 2 {
+            return None;
+        }
+
+        let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
+        let prefix = ((opcode & 0xf000) >> 12) as u8;
+        let x = ((opcode & 0x0f00) >> 8) as u8;
+        let y = ((opcode & 0x00f0) >> 4) as u8;
+        let n = (opcode & 0x000f) as u8;
+        let nn = (opcode & 0x00ff) as u8;
+        let nnn = opcode & 0x0fff;
+
+        let instruction = match prefix {
+            0x0 => match opcode {
+                0x00c0 ..= 0x00cf => Instruction::ScrollDown(n),
+                0x00d0 ..= 0x00df => Instruction::ScrollUp(n),
+                0x00e0 => Instruction::Clear,
+                0x00ee => Instruction::Return,
+                0x00fb => Instruction::ScrollRight,
+                0x00fc => Instruction::ScrollLeft,
+                0x00fd => Instruction::Exit,
+                0x00fe => Instruction::Lores,
+                0x00ff => Instruction::Hires,
+                _ => Instruction::Sys(nnn),
+            },
+            0x1 => Instruction::Jump(nnn),
+            0x2 => Instruction::Call(nnn),
+            0x3 => Instruction::SkipEqualByte(x, nn),
+            0x4 => Instruction::SkipNotEqualByte(x, nn),
+            0x5 => match n {
+                0x0 => Instruction::SkipEqual(x, y),
+                0x2 => Instruction::StoreRange(x, y),
+                0x3 => Instruction::LoadRange(x, y),
+                _ => return None,
+            },
+            0x6 => Instruction::LoadByte(x, nn),
+            0x7 => Instruction::AddByte(x, nn),
+            0x8 => match n {
+                0x0 => Instruction::Move(x, y),
+                0x1 => Instruction::Or(x, y),
+                0x2 => Instruction::And(x, y),
+                0x3 => Instruction::Xor(x, y),
+                0x4 => Instruction::Add(x, y),
+                0x5 => Instruction::Sub(x, y),
+                0x6 => Instruction::ShiftRight(x, y),
+                0x7 => Instruction::SubReverse(x, y),
+                0xe => Instruction::ShiftLeft(x, y),
+                _ => return None,
+            },
+            0x9 if n == 0 => Instruction::SkipNotEqual(x, y),
+            0xa => Instruction::LoadI(nnn),
+            0xb => Instruction::JumpOffset(nnn),
+            0xc => Instruction::Random(x, nn),
+            0xd => Instruction::Draw(x, y, n),
+            0xe => match nn {
+                0x9e => Instruction::SkipKey(x),
+                0xa1 => Instruction::SkipNotKey(x),
+                _ => return None,
+            },
+            0xf => match nn {
+                0x00 if x == 0 => {
+                    if bytes.len() < 4 {
+                        return None;
+                    }
+                    Instruction::LoadILong((bytes[2] as u16) << 8 | bytes[3] as u16)
+                },
+                0x01 => Instruction::Plane(x),
+                0x02 if x == 0 => Instruction::Audio,
+                0x07 => Instruction::GetDelay(x),
+                0x0a => Instruction::WaitKey(x),
+                0x15 => Instruction::SetDelay(x),
+                0x18 => Instruction::SetSound(x),
+                0x1e => Instruction::AddI(x),
+                0x29 => Instruction::Font(x),
+                0x30 => Instruction::BigFont(x),
+                0x33 => Instruction::Bcd(x),
+                0x3a => Instruction::Pitch(x),
+                0x55 => Instruction::Store(x),
+                0x65 => Instruction::Load(x),
+                0x75 => Instruction::StoreFlags(x),
+                0x85 => Instruction::LoadFlags(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Encode the instruction into its 2 or 4 bytes of machine code.
+    ///
+    /// Operands that are too large for their field are truncated.
+    pub fn encode(&self) -> Vec<u8> {
+        fn xy(prefix: u16, x: u8, y: u8, n: u16) -> u16 {
+            prefix << 12 | (x as u16 & 0xf) << 8 | (y as u16 & 0xf) << 4 | n
+        }
+        fn xnn(prefix: u16, x: u8, nn: u8) -> u16 {
+            prefix << 12 | (x as u16 & 0xf) << 8 | nn as u16
+        }
+        fn nnn(prefix: u16, address: u16) -> u16 {
+            prefix << 12 | (address & 0xfff)
+        }
+
+        let opcode = match *self {
+            Instruction::Sys(address) => nnn(0x0, address),
+            Instruction::ScrollDown(n) => 0x00c0 | (n as u16 & 0xf),
+            Instruction::ScrollUp(n) => 0x00d0 | (n as u16 & 0xf),
+            Instruction::Clear => 0x00e0,
+            Instruction::Return => 0x00ee,
+            Instruction::ScrollRight => 0x00fb,
+            Instruction::ScrollLeft => 0x00fc,
+            Instruction::Exit => 0x00fd,
+            Instruction::Lores => 0x00fe,
+            Instruction::Hires => 0x00ff,
+            Instruction::Jump(address) => nnn(0x1, address),
+            Instruction::Call(address) => nnn(0x2, address),
+            Instruction::SkipEqualByte(x, byte) => xnn(0x3, x, byte),
+            Instruction::SkipNotEqualByte(x, byte) => xnn(0x4, x, byte),
+            Instruction::SkipEqual(x, y) => xy(0x5, x, y, 0x0),
+            Instruction::StoreRange(x, y) => xy(0x5, x, y, 0x2),
+            Instruction::LoadRange(x, y) => xy(0x5, x, y, 0x3),
+            Instruction::LoadByte(x, byte) => xnn(0x6, x, byte),
+            Instruction::AddByte(x, byte) => xnn(0x7, x, byte),
+            Instruction::Move(x, y) => xy(0x8, x, y, 0x0),
+            Instruction::Or(x, y) => xy(0x8, x, y, 0x1),
+            Instruction::And(x, y) => xy(0x8, x, y, 0x2),
+            Instruction::Xor(x, y) => xy(0x8, x, y, 0x3),
+            Instruction::Add(x, y) => xy(0x8, x, y, 0x4),
+            Instruction::Sub(x, y) => xy(0x8, x, y, 0x5),
+            Instruction::ShiftRight(x, y) => xy(0x8, x, y, 0x6),
+            Instruction::SubReverse(x, y) => xy(0x8, x, y, 0x7),
+            Instruction::ShiftLeft(x, y) => xy(0x8, x, y, 0xe),
+            Instruction::SkipNotEqual(x, y) => xy(0x9, x, y, 0x0),
+            Instruction::LoadI(address) => nnn(0xa, address),
+            Instruction::JumpOffset(address) => nnn(0xb, address),
+            Instruction::Random(x, byte) => xnn(0xc, x, byte),
+            Instruction::Draw(x, y, n) => xy(0xd, x, y, n as u16 & 0xf),
+            Instruction::SkipKey(x) => xnn(0xe, x, 0x9e),
+            Instruction::SkipNotKey(x) => xnn(0xe, x, 0xa1),
+            Instruction::LoadILong(address) => {
+                return vec![0xf0, 0x00, (address >> 8) as u8, address as u8];
+            },
+            Instruction::Plane(n) => xnn(0xf, n, 0x01),
+            Instruction::Audio => 0xf002,
+            Instruction::GetDelay(x) => xnn(0xf, x, 0x07),
+            Instruction::WaitKey(x) => xnn(0xf, x, 0x0a),
+            Instruction::SetDelay(x) => xnn(0xf, x, 0x15),
+            Instruction::SetSound(x) => xnn(0xf, x, 0x18),
+            Instruction::AddI(x) => xnn(0xf, x, 0x1e),
+            Instruction::Font(x) => xnn(0xf, x, 0x29),
+            Instruction::BigFont(x) => xnn(0xf, x, 0x30),
+            Instruction::Bcd(x) => xnn(0xf, x, 0x33),
+            Instruction::Pitch(x) => xnn(0xf, x, 0x3a),
+            Instruction::Store(x) => xnn(0xf, x, 0x55),
+            Instruction::Load(x) => xnn(0xf, x, 0x65),
+            Instruction::StoreFlags(x) => xnn(0xf, x, 0x75),
+            Instruction::LoadFlags(x) => xnn(0xf, x, 0x85),
+        };
+
+        vec![(opcode >> 8) as u8, opcode as u8]
+    }
+
+    /// The size of the instruction in bytes
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LoadILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Returns true if the instruction may skip the one after it
+    pub fn is_skip(&self) -> bool {
+        matches!(*self,
+            Instruction::SkipEqualByte(..)
+            | Instruction::SkipNotEqualByte(..)
+            | Instruction::SkipEqual(..)
+            | Instruction::SkipNotEqual(..)
+            | Instruction::SkipKey(_)
+            | Instruction::SkipNotKey(_))
+    }
+}
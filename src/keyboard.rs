@@ -0,0 +1,162 @@
+extern crate sdl2;
+extern crate chip8;
+
+use sdl2::EventPump;
+use sdl2::event::Event;
+use sdl2::keyboard::{ Mod, Scancode, LCTRLMOD, RCTRLMOD };
+
+use chip8::Chip8;
+use chip8::frontend::Input;
+
+use controller::Controllers;
+use keymap::{ HostKey, KeyMode, Keymap, KEYPAD_LAYOUT };
+
+/// Reads the keypad from the host keyboard and game controllers, and
+/// collects the presses of the keys that control the emulator itself.
+pub struct Keyboard {
+    event_pump: EventPump,
+    controllers: Controllers,
+
+    /// Which host keys press each keypad key
+    keymap: Keymap,
+
+    /// While keys are being bound, the position in ``KEYPAD_LAYOUT`` of
+    /// the one that the next key pressed is bound to
+    binding: Option<usize>,
+
+    /// Set once every key has been bound, until it is taken
+    rebound: bool,
+
+    /// Presses of hotkeys since they were last taken
+    hotkeys: Vec<(Scancode, Mod)>,
+}
+
+impl Keyboard {
+    pub fn new(event_pump: EventPump, controllers: Controllers, keymap: Keymap) -> Keyboard {
+        Keyboard {
+            event_pump,
+            controllers,
+            keymap,
+            binding: None,
+            rebound: false,
+            hotkeys: Vec::new(),
+        }
+    }
+
+    /// Check if a key is held down
+    pub fn is_pressed(&self, scancode: Scancode) -> bool {
+        self.event_pump.keyboard_state().is_scancode_pressed(scancode)
+    }
+
+    /// Take the hotkey presses collected since the last call
+    pub fn take_hotkeys(&mut self) -> Vec<(Scancode, Mod)> {
+        std::mem::take(&mut self.hotkeys)
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Start binding the keypad keys one at a time, in the order they are
+    /// laid out, to the next key pressed. Escape keeps a key's binding.
+    pub fn start_binding(&mut self) {
+        self.binding = Some(0);
+        self.prompt_binding();
+    }
+
+    /// Check if keypad keys are being bound, during which the keypad reads
+    /// as released
+    pub fn is_binding(&self) -> bool {
+        self.binding.is_some()
+    }
+
+    /// Check if every key has been bound since the last call
+    pub fn take_rebound(&mut self) -> bool {
+        std::mem::replace(&mut self.rebound, false)
+    }
+
+    /// Ask for the key to bind to the keypad key being bound
+    fn prompt_binding(&self) {
+        if let Some(position) = self.binding {
+            let key = KEYPAD_LAYOUT[position];
+            let current: Vec<String> = self.keymap.host_keys(key).iter().map(|host_key| host_key.to_string()).collect();
+            println!("Press the key for keypad {:X} (Escape keeps {})", key, current.join(", "));
+        }
+    }
+
+    /// Bind a host key to the keypad key being bound, and move on to the
+    /// next one
+    fn bind(&mut self, host_key: Option<HostKey>) {
+        let position = match self.binding {
+            Some(position) => position,
+            None => return,
+        };
+        if let Some(host_key) = host_key {
+            self.keymap.bind(KEYPAD_LAYOUT[position], &[host_key]);
+        }
+
+        if position + 1 < KEYPAD_LAYOUT.len() {
+            self.binding = Some(position + 1);
+            self.prompt_binding();
+        } else {
+            self.binding = None;
+            self.rebound = true;
+        }
+    }
+}
+
+impl Input for Keyboard {
+    fn poll(&mut self, chip: &mut Chip8) -> bool {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            self.controllers.handle_event(&event);
+            match event {
+                Event::Quit{ .. } => return false,
+
+                Event::KeyDown{ scancode: Some(scancode), keymod, repeat: false, .. } if is_hotkey(scancode, keymod) =>
+                    self.hotkeys.push((scancode, keymod)),
+
+                Event::KeyDown{ scancode: Some(scancode), keycode, repeat: false, .. } if self.is_binding() => {
+                    let host_key = match (scancode, self.keymap.mode()) {
+                        (Scancode::Escape, _) => None,
+                        (_, KeyMode::Scancode) => Some(HostKey::Scancode(scancode)),
+                        (_, KeyMode::Keycode) => keycode.map(HostKey::Keycode),
+                    };
+                    self.bind(host_key);
+                },
+
+                _ => (),
+            }
+        }
+
+        // a keypad key is down if any of the host keys or controller
+        // buttons bound to it are
+        let keyboard_state = self.event_pump.keyboard_state();
+        let mut pressed = [false; 16];
+        if !self.is_binding() {
+            for &(host_key, key) in self.keymap.bindings() {
+                if let Some(scancode) = host_key.scancode() {
+                    pressed[key] |= keyboard_state.is_scancode_pressed(scancode);
+                }
+            }
+            self.controllers.press_keys(&mut pressed);
+        }
+        for (key, &pressed) in pressed.iter().enumerate() {
+            chip.set_input(key, pressed);
+        }
+
+        true
+    }
+}
+
+/// Returns true for the keys that control the emulator rather than the keypad:
+/// the function keys, and Ctrl + M
+fn is_hotkey(scancode: Scancode, keymod: Mod) -> bool {
+    match scancode {
+        Scancode::F1 | Scancode::F2 | Scancode::F3 | Scancode::F4 | Scancode::F5 |
+        Scancode::F6 | Scancode::F7 | Scancode::F8 | Scancode::F9 | Scancode::F10 |
+        Scancode::F11 | Scancode::F12 => true,
+        Scancode::M => keymod.intersects(LCTRLMOD | RCTRLMOD),
+        _ => false,
+    }
+}
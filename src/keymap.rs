@@ -0,0 +1,250 @@
+extern crate sdl2;
+
+use sdl2::keyboard::{ Keycode, Scancode };
+
+use std::fmt;
+use std::fs::File;
+use std::io::{ Read, Write };
+
+/// The order keypad keys are laid out in, row by row
+pub const KEYPAD_LAYOUT: [usize; 16] = [
+    0x1, 0x2, 0x3, 0xc,
+    0x4, 0x5, 0x6, 0xd,
+    0x7, 0x8, 0x9, 0xe,
+    0xa, 0x0, 0xb, 0xf,
+];
+
+/// How host keys are identified
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyMode {
+    /// By their position on the keyboard, whatever the layout. The default
+    /// keypad sits in the same place on QWERTY, AZERTY and Dvorak keyboards.
+    Scancode,
+
+    /// By the character printed on them in the current layout
+    Keycode,
+}
+
+impl fmt::Display for KeyMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            KeyMode::Scancode => write!(f, "scancode"),
+            KeyMode::Keycode => write!(f, "keycode"),
+        }
+    }
+}
+
+/// A key on the host keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostKey {
+    Scancode(Scancode),
+    Keycode(Keycode),
+}
+
+impl HostKey {
+    /// Look up a key by the name SDL gives it
+    pub fn from_name(name: &str, mode: KeyMode) -> Option<HostKey> {
+        match mode {
+            KeyMode::Scancode => Scancode::from_name(name).map(HostKey::Scancode),
+            KeyMode::Keycode => Keycode::from_name(name).map(HostKey::Keycode),
+        }
+    }
+
+    /// Get the physical key, in the current keyboard layout
+    pub fn scancode(self) -> Option<Scancode> {
+        match self {
+            HostKey::Scancode(scancode) => Some(scancode),
+            HostKey::Keycode(keycode) => Scancode::from_keycode(keycode),
+        }
+    }
+}
+
+impl fmt::Display for HostKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HostKey::Scancode(scancode) => write!(f, "{}", scancode.name()),
+            HostKey::Keycode(keycode) => write!(f, "{}", keycode.name()),
+        }
+    }
+}
+
+/// Which host keys press each key of the Chip8 hex keypad
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// How keys are named in files and captured when binding them
+    mode: KeyMode,
+
+    /// Pairs of a host key and the keypad key it presses. A keypad key may
+    /// be bound to any number of host keys.
+    bindings: Vec<(HostKey, usize)>,
+}
+
+impl Default for Keymap {
+    /// The keypad laid out on the left of a QWERTY keyboard:
+    ///
+    /// ```text
+    /// 1 2 3 C      1 2 3 4
+    /// 4 5 6 D  ->  Q W E R
+    /// 7 8 9 E      A S D F
+    /// A 0 B F      Z X C V
+    /// ```
+    fn default() -> Keymap {
+        let layout = [
+            Scancode::Num1, Scancode::Num2, Scancode::Num3, Scancode::Num4,
+            Scancode::Q, Scancode::W, Scancode::E, Scancode::R,
+            Scancode::A, Scancode::S, Scancode::D, Scancode::F,
+            Scancode::Z, Scancode::X, Scancode::C, Scancode::V,
+        ];
+        Keymap {
+            mode: KeyMode::Scancode,
+            bindings: layout.iter()
+                .zip(KEYPAD_LAYOUT.iter())
+                .map(|(&scancode, &key)| (HostKey::Scancode(scancode), key))
+                .collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Change bindings according to the contents of a keymap file.
+    ///
+    /// Each line binds a keypad key, as a hex digit, to one or more host
+    /// keys separated by commas, for example ``5 = W, Up``. Host keys are
+    /// named as SDL names them. A line ``mode = keycode`` makes the lines
+    /// after it name keys by the character they type in the current layout
+    /// rather than by where they are, and ``mode = scancode`` switches
+    /// back. A keypad key may be given on several lines, which add up, and
+    /// a line with no host keys, such as ``5 =``, leaves it unbound. Keypad
+    /// keys that are not mentioned keep their binding. Blank lines
+    /// and lines starting with ``#`` or ``;`` are ignored.
+    pub fn apply(&mut self, source: &str) -> Result<(), String> {
+        // the first line for a key replaces its old binding
+        let mut mentioned = [false; 16];
+
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let mut parts = line.splitn(2, '=');
+            let key = parts.next().unwrap_or("").trim();
+            let names = match parts.next() {
+                Some(names) => names.trim(),
+                None => return Err(format!("line {}: expected '<hex key> = <host keys>'", number + 1)),
+            };
+
+            if key.eq_ignore_ascii_case("mode") {
+                self.mode = match names.to_ascii_lowercase().as_str() {
+                    "scancode" => KeyMode::Scancode,
+                    "keycode" => KeyMode::Keycode,
+                    _ => return Err(format!("line {}: unknown mode '{}', expected scancode or keycode",
+                                            number + 1, names)),
+                };
+                continue;
+            }
+
+            let key = match usize::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(format!("line {}: '{}' is not a keypad key, 0 - F", number + 1, key)),
+            };
+
+            let mut host_keys = Vec::new();
+            if !names.is_empty() {
+                for name in names.split(',').map(str::trim) {
+                    match HostKey::from_name(name, self.mode) {
+                        Some(host_key) => host_keys.push(host_key),
+                        None => return Err(format!("line {}: unknown key '{}'", number + 1, name)),
+                    }
+                }
+            }
+            if !mentioned[key] {
+                mentioned[key] = true;
+                self.bind(key, &[]);
+            }
+            self.bindings.extend(host_keys.into_iter().map(|host_key| (host_key, key)));
+        }
+
+        Ok(())
+    }
+
+    /// Read a keymap file and apply it on top of the current bindings
+    pub fn load(&mut self, path: &str) -> Result<(), String> {
+        let mut source = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut source))
+            .map_err(|err| format!("{}: {}", path, err))?;
+        self.apply(&source).map_err(|err| format!("{}:{}", path, err))
+    }
+
+    /// Write every binding to a keymap file
+    pub fn save(&self, path: &str) -> std::io::Result<()> {
+        File::create(path)?.write_all(self.to_string().as_bytes())
+    }
+
+    pub fn mode(&self) -> KeyMode {
+        self.mode
+    }
+
+    /// Replace the host keys bound to a keypad key
+    pub fn bind(&mut self, key: usize, host_keys: &[HostKey]) {
+        self.bindings.retain(|&(_, bound)| bound != key);
+        self.bindings.extend(host_keys.iter().map(|&host_key| (host_key, key)));
+    }
+
+    /// Get the host keys bound to a keypad key
+    pub fn host_keys(&self, key: usize) -> Vec<HostKey> {
+        self.bindings.iter()
+            .filter(|&&(_, bound)| bound == key)
+            .map(|&(host_key, _)| host_key)
+            .collect()
+    }
+
+    /// Get the host keys and the keypad keys they press
+    pub fn bindings(&self) -> &[(HostKey, usize)] {
+        &self.bindings
+    }
+}
+
+impl fmt::Display for Keymap {
+    /// Write the keymap in the format ``apply()`` reads
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut mode = None;
+        for &key in KEYPAD_LAYOUT.iter() {
+            let host_keys = self.host_keys(key);
+
+            // keys are written in the mode they were bound in, which isn't
+            // necessarily the same for all of them
+            let mut names = Vec::new();
+            for host_key in host_keys {
+                let key_mode = match host_key {
+                    HostKey::Scancode(_) => KeyMode::Scancode,
+                    HostKey::Keycode(_) => KeyMode::Keycode,
+                };
+                if mode != Some(key_mode) {
+                    if !names.is_empty() {
+                        writeln!(f, "{:X} = {}", key, names.join(", "))?;
+                        names.clear();
+                    }
+                    writeln!(f, "mode = {}", key_mode)?;
+                    mode = Some(key_mode);
+                }
+                names.push(host_key.to_string());
+            }
+
+            // unbound keys are written too, or they would get their
+            // default binding back when the file is loaded
+            if names.is_empty() {
+                writeln!(f, "{:X} =", key)?;
+            } else {
+                writeln!(f, "{:X} = {}", key, names.join(", "))?;
+            }
+        }
+
+        // keys bound later are captured in the keymap's own mode
+        if mode != Some(self.mode) {
+            writeln!(f, "mode = {}", self.mode)?;
+        }
+        Ok(())
+    }
+}
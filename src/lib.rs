@@ -1,9 +1,43 @@
-extern crate rand;
-
 use std::fs::File;
-use std::io::Read;
-use std::time::{Duration, Instant};
-use rand::Rng;
+use std::io::{Read, Write};
+use std::ops::Range;
+
+mod error;
+pub use error::EmulationError;
+
+mod quirks;
+pub use quirks::Quirks;
+
+mod rng;
+pub use rng::Rng;
+
+mod state;
+pub use state::{ StateError, STATE_VERSION };
+
+mod instruction;
+pub use instruction::Instruction;
+
+pub mod disasm;
+
+pub mod asm;
+
+pub mod debugger;
+
+pub mod gdb;
+
+pub mod headless;
+
+pub mod frontend;
+
+pub mod palette;
+
+pub mod filter;
+
+pub mod audio;
+
+pub mod rewind;
+
+mod png;
 
 /// The width of the Chip8 display
 pub const DISPLAY_WIDTH: usize = 64;
@@ -14,6 +48,31 @@ pub const DISPLAY_HEIGHT: usize = 32;
 /// The total number of pixels in the Chip8 display buffer
 pub const DISPLAY_SIZE: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT;
 
+/// The width of the display in SUPER-CHIP high resolution mode
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+
+/// The height of the display in SUPER-CHIP high resolution mode
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// The total number of pixels in the high resolution display buffer
+pub const HIRES_DISPLAY_SIZE: usize = HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT;
+
+/// The amount of RAM in bytes. The original Chip8 only had 4KB, but
+/// XO-CHIP programs can address a full 64KB.
+pub const MEMORY_SIZE: usize = 0x10000;
+
+/// The address programs are loaded at, and start executing from
+pub const PROGRAM_START: usize = 0x200;
+
+/// The largest ROM that fits in memory after the interpreter area
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - PROGRAM_START;
+
+/// The address of the large SUPER-CHIP font in memory, just after ``FONT``
+const BIG_FONT_ADDRESS: usize = 0x50;
+
+/// The XO-CHIP pitch register value that plays audio patterns at 4000Hz
+const DEFAULT_PITCH: u8 = 64;
+
 /// The default fontset for the Chip8 contains sprites for each
 /// hexadecimal digit (0 - F).
 ///
@@ -38,14 +97,55 @@ pub const FONT: [u8; 80] = [
     0xf0, 0x80, 0xf0, 0x80, 0x80  // F
 ];
 
+/// The large fontset introduced by SUPER-CHIP for high resolution mode.
+///
+/// Each sprite is 10 bytes long (8x10 pixel sprites). SUPER-CHIP 1.1 only
+/// shipped the digits 0 - 9, the letters A - F follow the XO-CHIP font.
+pub const BIG_FONT: [u8; 160] = [
+    0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff, // 1
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // 2
+    0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 3
+    0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03, // 4
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 5
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 6
+    0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18, // 7
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, // 8
+    0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff, // 9
+    0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // A
+    0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, // B
+    0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c, // C
+    0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc, // D
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, // E
+    0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0  // F
+];
+
+/// The progress of an fx0a instruction waiting for a key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyWait {
+    /// Not waiting
+    Idle,
+
+    /// Waiting for a key to be pressed
+    Waiting,
+
+    /// A key has been pressed, and the wait ends once it is released
+    Pressed(u8),
+
+    /// The key has been released, and is stored in Vx the next time fx0a
+    /// runs
+    Released(u8),
+}
+
 /// A Structure that emulates the architecture of the Chip8 computer.
+#[derive(Clone)]
 pub struct Chip8 {
-    /// The Chip8 has 4KB of RAM.
+    /// The Chip8 has 4KB of RAM, extended to 64KB by XO-CHIP.
     ///
     /// The original Interpreter and fonts takes up the first
     /// 512 bytes of ram, so program roms can use the space
-    /// from 0x200 - 0xfff
-    mem: [u8; 0x1000],
+    /// from 0x200 - 0xffff
+    mem: Vec<u8>,
     
     /// The Chip8 has 16 8-bit registers ranging from v0 to vf
     v: [u8; 0x10],
@@ -74,71 +174,81 @@ pub struct Chip8 {
     
     /// Chip8 computers have a 16-key hexadecimal keypad with keys 0 - F.
     input: [bool; 0x10],
+
+    /// Tracks the key press and release that fx0a waits for
+    key_wait: KeyWait,
     
-    /// Chip8 computers have a 64 x 32 pixel display.
-    display: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+    /// Chip8 computers have a 64 x 32 pixel display. SUPER-CHIP adds a
+    /// 128 x 64 high resolution mode. In low resolution mode only the
+    /// top-left 64 x 32 pixels of the buffer are used.
+    ///
+    /// XO-CHIP splits the display into two bitplanes, so each pixel holds
+    /// 2 bits: bit 0 for the first plane and bit 1 for the second.
+    display: [[u8; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT],
+
+    /// The XO-CHIP bitplanes that drawing, clearing and scrolling affect
+    plane: u8,
+
+    /// The XO-CHIP 1-bit audio pattern, played back while the sound timer
+    /// is active.
+    pattern: [u8; 0x10],
+
+    /// The XO-CHIP pitch register, which selects the pattern playback rate
+    pitch: u8,
+
+    /// Whether the display is in SUPER-CHIP high resolution mode
+    hires: bool,
+
+    /// SUPER-CHIP programs can save up to 16 registers into the HP48's
+    /// RPL user flags, which survive after the program exits.
+    rpl: [u8; 0x10],
+
+    /// Set once the program has executed the SUPER-CHIP exit instruction
+    exited: bool,
 
     /// This flag is enabled when the chip executes the draw command
     render_flag: bool,
 
-    /// Used to keep the timers ticking down at 60Hz
-    last_cycle: Instant,
-}
+    /// Set whenever the timers tick, and cleared when a sprite is drawn
+    /// while waiting for the display.
+    vblank: bool,
 
-/// Print a warning that the given opcode was unsupported
-fn unsupported_opcode(opcode: u16, pc: u16) {
-    println!("[WARNING] opcode 0x{:X} from pc 0x{:X} is unsupported", opcode, pc);
+    /// Selects the behaviour of the ambiguous instructions
+    quirks: Quirks,
+
+    /// The source of the random numbers used by cxkk
+    rng: Rng,
 }
 
 impl Chip8 {
     /// Create a Chip8 device and load the specified ROM file into it.
-    pub fn from_rom_file(rom_file: &str) -> std::io::Result<Chip8> {
-        let mut ram: [u8; 0x1000] = [0; 0x1000];
-        
-        // load rom data into memory
-        let mut rom_data: [u8; 0xe00] = [0; 0xe00];
-        let mut file = File::open(rom_file)?;
-        file.read(&mut rom_data)?;
-        for i in 0 .. 0xe00 {
-            ram[i + 0x200] = rom_data[i];
-        }
-        
-        // load fonts into memory
-        for i in 0 .. 80 {
-            ram[i] = FONT[i];
-        }
-        
-        Ok(Chip8 {
-            mem: ram,
-            v: [0; 0x10],
-            dt: 0,
-            st: 0,
-            sp: 0,
-            stack: [0; 0x10],
-            i: 0,
-            pc: 0x200,
-            input: [false; 0x10],
-            display: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
-            render_flag: false,
-            last_cycle: Instant::now(),
-        })
+    pub fn from_rom_file(rom_file: &str, quirks: Quirks) -> std::io::Result<Chip8> {
+        let mut rom_data = Vec::new();
+        File::open(rom_file)?.read_to_end(&mut rom_data)?;
+        Chip8::with_rom_data(&rom_data, quirks)
     }
     
     /// Create a Chip8 device and load the given ROM data into it.
-    pub fn with_rom_data(rom_data: [u8; 0xe00]) -> Chip8 {
-        let mut ram: [u8; 0x1000] = [0; 0x1000];
+    ///
+    /// Fails if the ROM is larger than ``MAX_ROM_SIZE`` bytes.
+    pub fn with_rom_data(rom_data: &[u8], quirks: Quirks) -> std::io::Result<Chip8> {
+        if rom_data.len() > MAX_ROM_SIZE {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("ROM is {} bytes, but at most {} bytes fit in memory",
+                        rom_data.len(), MAX_ROM_SIZE)));
+        }
+
+        let mut ram = vec![0; MEMORY_SIZE];
         
         // load rom data into memory
-        for i in 0 .. 0xe00 {
-            ram[i + 0x200] = rom_data[i];
-        }
+        ram[PROGRAM_START .. PROGRAM_START + rom_data.len()].copy_from_slice(rom_data);
         
         // load fonts into memory
-        for i in 0 .. 80 {
-            ram[i] = FONT[i];
-        }
+        ram[.. FONT.len()].copy_from_slice(&FONT);
+        ram[BIG_FONT_ADDRESS .. BIG_FONT_ADDRESS + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
         
-        Chip8 {
+        Ok(Chip8 {
             mem: ram,
             v: [0; 0x10],
             dt: 0,
@@ -146,68 +256,269 @@ impl Chip8 {
             sp: 0,
             stack: [0; 0x10],
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START as u16,
             input: [false; 0x10],
-            display: [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            key_wait: KeyWait::Idle,
+            display: [[0; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT],
+            plane: 0x1,
+            pattern: [0; 0x10],
+            pitch: DEFAULT_PITCH,
+            hires: false,
+            rpl: [0; 0x10],
+            exited: false,
             render_flag: false,
-            last_cycle: Instant::now(),
-        }
+            vblank: false,
+            quirks,
+            rng: Rng::from_entropy(),
+        })
     }
     
+    /// Get the quirks the Chip8 was built with
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Reseed the random number generator used by cxkk.
+    ///
+    /// A new Chip8 is seeded from the system clock. Seeding it with a fixed
+    /// value right after creating it makes every run of a ROM identical.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Get the random number generator used by cxkk
+    pub fn get_rng(&self) -> Rng {
+        self.rng
+    }
+
+    /// Replace the random number generator used by cxkk
+    pub fn set_rng(&mut self, rng: Rng) {
+        self.rng = rng;
+    }
+
     /// Get the state of the render flag
     pub fn get_render_flag(&self) -> bool {
         self.render_flag
     }
 
-    /// Performs a single Chip8 operation, and updates timers
-    pub fn tick(&mut self) {
-        let opcode: u16 = (self.mem[self.pc as usize] as u16) << 8; self.pc += 1;
-        let opcode: u16 = opcode | (self.mem[self.pc as usize] as u16); self.pc += 1;
+    /// Performs a single Chip8 operation
+    ///
+    /// The timers are not updated, call ``tick_timers()`` once per emulated
+    /// frame for that, or use ``run_frame()`` which does both.
+    ///
+    /// If the instruction cannot be executed, the machine is left as it was
+    /// before the instruction (apart from the program counter, which has moved
+    /// past it) and the reason is returned. It is up to the caller to decide
+    /// whether to stop, log the error, or keep ticking. If the program counter
+    /// is out of bounds there is no instruction to move past, so nothing
+    /// changes and every tick fails until the program counter is set.
+    pub fn tick(&mut self) -> Result<(), EmulationError> {
+        let pc = self.pc;
+        if pc as usize + 1 >= self.mem.len() {
+            return Err(EmulationError::PcOutOfBounds { pc });
+        }
+
+        let opcode: u16 = (self.mem[self.pc as usize] as u16) << 8;
+        let opcode: u16 = opcode | (self.mem[self.pc as usize + 1] as u16);
+        self.pc = self.pc.wrapping_add(2);
      
         self.render_flag = false;
 
         // Execute the instruction at PC
-        self.execute_opcode(opcode);
-        
-        // Timers decrement themselves at a rate of 60Hz
-        if self.last_cycle.elapsed() >= Duration::from_millis(17) {
-            self.last_cycle = Instant::now();
-            if self.dt > 0 { self.dt -= 1; }
-            if self.st > 0 { self.st -= 1; }
+        self.execute_opcode(opcode, pc)
+    }
+
+    /// Decrements the delay and sound timers, and signals a vertical blank.
+    ///
+    /// On the real hardware this happens 60 times per second, so frontends
+    /// should call it once for every 1/60th of a second of emulated time.
+    pub fn tick_timers(&mut self) {
+        if self.dt > 0 { self.dt -= 1; }
+        if self.st > 0 { self.st -= 1; }
+        self.vblank = true;
+    }
+
+    /// Emulates one 60Hz frame: performs ``instructions`` Chip8 operations
+    /// and then ticks the timers.
+    ///
+    /// Afterwards the render flag is set if any of the operations changed
+    /// the display. If an operation fails, the rest of the frame is
+    /// abandoned without ticking the timers and the error is returned.
+    pub fn run_frame(&mut self, instructions: usize) -> Result<(), EmulationError> {
+        let mut rendered = false;
+        for _ in 0 .. instructions {
+            let result = self.tick();
+            rendered |= self.render_flag;
+            self.render_flag = rendered;
+            result?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Get the range of ``len`` bytes of RAM starting at ``start``, or an error
+    /// if any of it lies outside of RAM.
+    fn mem_range(&self, opcode: u16, pc: u16, start: usize, len: usize)
+        -> Result<Range<usize>, EmulationError>
+    {
+        if start + len > self.mem.len() {
+            let address = std::cmp::max(start, self.mem.len());
+            return Err(EmulationError::MemoryOutOfBounds { opcode, pc, address });
+        }
+        Ok(start .. start + len)
+    }
+    
+    /// Skips the next instruction, which is 4 bytes long if it is an
+    /// XO-CHIP long load (f000 nnnn).
+    fn skip_next(&mut self) {
+        let pc = self.pc as usize;
+        let long_load = pc + 1 < self.mem.len()
+            && self.mem[pc] == 0xf0 && self.mem[pc + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long_load { 4 } else { 2 });
+    }
+
+    /// Turns off every pixel of the display buffer in the given bitplanes
+    fn clear_display(&mut self, planes: u8) {
+        for row in self.display.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !planes;
+            }
+        }
+    }
+
+    /// Get the width in pixels and the length in bytes of the sprite drawn
+    /// by dxyn
+    fn sprite_size(&self, n: u8) -> (usize, usize) {
+        match n {
+            0 if self.quirks.lores_dxy0_8x16 && !self.hires => (8, 16),
+            0 => (16, 32),
+            n => (8, n as usize),
+        }
+    }
+
+    /// Draws a sprite ``width`` pixels wide at (x, y) into the given bitplane,
+    /// reading its rows from the given range of memory. Returns true if any
+    /// lit pixel was erased.
+    fn draw_sprite(&mut self, x: usize, y: usize, width: usize, plane: u8, rows: Range<usize>) -> bool {
+        let display_width = self.display_width();
+        let display_height = self.display_height();
+        let bytes_per_row = width / 8;
+
+        let origin_x = x % display_width;
+        let origin_y = y % display_height;
+        let mut collision = false;
+
+        for row in 0 .. rows.len() / bytes_per_row {
+            let y = origin_y + row;
+            if self.quirks.clip_sprites && y >= display_height {
+                break;
+            }
+            let y = y % display_height;
+
+            for column in 0 .. width {
+                let x = origin_x + column;
+                if self.quirks.clip_sprites && x >= display_width {
+                    break;
+                }
+                let x = x % display_width;
+                let sprite = self.mem[rows.start + row * bytes_per_row + column / 8];
+                if (sprite >> (7 - column % 8)) & 0x1 == 0x1 {
+                    if self.display[y][x] & plane != 0 {
+                        collision = true;
+                    }
+                    self.display[y][x] ^= plane;
+                }
+            }
         }
+
+        collision
+    }
+
+    /// Moves the contents of the selected bitplanes by (dx, dy) pixels.
+    /// Pixels scrolled in from outside the display are turned off.
+    fn scroll_display(&mut self, dx: isize, dy: isize) {
+        let width = self.display_width() as isize;
+        let height = self.display_height() as isize;
+        let mut scrolled = self.display;
+
+        for y in 0 .. height {
+            for x in 0 .. width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let pixel = if from_x >= 0 && from_x < width && from_y >= 0 && from_y < height {
+                    self.display[from_y as usize][from_x as usize] & self.plane
+                } else {
+                    0
+                };
+                let target = &mut scrolled[y as usize][x as usize];
+                *target = (*target & !self.plane) | pixel;
+            }
+        }
+
+        self.display = scrolled;
+        self.render_flag = true;
     }
     
-    /// Executes the given opcode
-    fn execute_opcode(&mut self, opcode: u16) {
+    /// Executes the given opcode, which was fetched from address ``pc``
+    fn execute_opcode(&mut self, opcode: u16, pc: u16) -> Result<(), EmulationError> {
         let prefix = ((opcode & 0xf000) >> 12) as u8;
         let x = ((opcode & 0x0f00) >> 8) as usize;
         let y = ((opcode & 0x00f0) >> 4) as usize;
         let n = (opcode & 0x000f) as u8;
         let nn = (opcode & 0x00ff) as u8;
-        let nnn = (opcode & 0x0fff) as u16;
+        let nnn = opcode & 0x0fff;
         
         match prefix {
             0x0 => {
                 match nn {
-                    // 00e0 clears the display
+                    // 00cn scrolls the display down by n pixels
+                    0xc0 ..= 0xcf => self.scroll_display(0, n as isize),
+
+                    // 00dn scrolls the display up by n pixels
+                    0xd0 ..= 0xdf => self.scroll_display(0, -(n as isize)),
+
+                    // 00e0 clears the selected bitplanes
                     0xe0 => {
-                        for y in 0 .. DISPLAY_HEIGHT {
-                            for x in 0 .. DISPLAY_WIDTH {
-                                self.display[y][x] = false;
-                            }
-                        }
+                        let planes = self.plane;
+                        self.clear_display(planes);
+                        self.render_flag = true;
                     },
                     
                     // 00ee returns from a subroutine
                     0xee => {
+                        if self.sp == 0 {
+                            return Err(EmulationError::StackUnderflow { opcode, pc });
+                        }
                         self.sp -= 1;
                         self.pc = self.stack[self.sp as usize];
                     },
-                    
-                    _ => {
-                        unsupported_opcode(opcode, self.pc);
-                        return;
+
+                    // 00fb scrolls the display right by 4 pixels
+                    0xfb => self.scroll_display(4, 0),
+
+                    // 00fc scrolls the display left by 4 pixels
+                    0xfc => self.scroll_display(-4, 0),
+
+                    // 00fd exits the interpreter
+                    0xfd => {
+                        self.exited = true;
+                        self.pc = pc;
+                    },
+
+                    // 00fe switches to low resolution mode
+                    0xfe => {
+                        self.hires = false;
+                        self.clear_display(0x3);
+                        self.render_flag = true;
+                    },
+
+                    // 00ff switches to high resolution mode
+                    0xff => {
+                        self.hires = true;
+                        self.clear_display(0x3);
+                        self.render_flag = true;
                     },
+                    
+                    _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
                 }
             },
             
@@ -216,19 +527,48 @@ impl Chip8 {
             
             // 2nnn calls the subroutine at nnn
             0x2 => {
+                if self.sp as usize >= self.stack.len() {
+                    return Err(EmulationError::StackOverflow { opcode, pc });
+                }
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
                 self.pc = nnn;
             },
             
             // 3xkk skips the next instruction if Vx == kk
-            0x3 => if self.v[x] == nn { self.pc += 2 },
+            0x3 => if self.v[x] == nn { self.skip_next() },
             
             // 4xkk skips next instruction if Vx != kk
-            0x4 => if self.v[x] != nn { self.pc += 2 },
+            0x4 => if self.v[x] != nn { self.skip_next() },
             
-            // 5xy0 skips next instruction if Vx == Vy
-            0x5 => if self.v[x] == self.v[y] { self.pc += 2 },
+            0x5 => {
+                match n {
+                    // 5xy0 skips next instruction if Vx == Vy
+                    0x0 => if self.v[x] == self.v[y] { self.skip_next() },
+
+                    // 5xy2 stores registers Vx - Vy into ram starting at location i.
+                    // If x > y, the registers are stored in reverse order.
+                    0x2 => {
+                        let range = self.mem_range(opcode, pc, self.i as usize, x.max(y) - x.min(y) + 1)?;
+                        for (offset, address) in range.enumerate() {
+                            let register = if x <= y { x + offset } else { x - offset };
+                            self.mem[address] = self.v[register];
+                        }
+                    },
+
+                    // 5xy3 fills registers Vx - Vy with data in ram at location i.
+                    // If x > y, the registers are filled in reverse order.
+                    0x3 => {
+                        let range = self.mem_range(opcode, pc, self.i as usize, x.max(y) - x.min(y) + 1)?;
+                        for (offset, address) in range.enumerate() {
+                            let register = if x <= y { x + offset } else { x - offset };
+                            self.v[register] = self.mem[address];
+                        }
+                    },
+
+                    _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
+                }
+            },
             
             // 6xkk sets Vx = kk
             0x6 => self.v[x] = nn,
@@ -242,13 +582,22 @@ impl Chip8 {
                     0x0 => self.v[x] = self.v[y],
                     
                     // 8xy1 sets Vx = Vx OR Vy
-                    0x1 => self.v[x] |= self.v[y],
+                    0x1 => {
+                        self.v[x] |= self.v[y];
+                        if self.quirks.vf_reset { self.v[0xf] = 0; }
+                    },
                     
                     // 8xy2 sets Vx = Vx AND Vy
-                    0x2 => self.v[x] &= self.v[y],
+                    0x2 => {
+                        self.v[x] &= self.v[y];
+                        if self.quirks.vf_reset { self.v[0xf] = 0; }
+                    },
                     
                     // 8xy3 sets Vx = Vx XOR Vy
-                    0x3 => self.v[x] ^= self.v[y],
+                    0x3 => {
+                        self.v[x] ^= self.v[y];
+                        if self.quirks.vf_reset { self.v[0xf] = 0; }
+                    },
                     
                     // 8xy4 sets Vx = Vx + Vy, sets Vf = carry
                     0x4 => {
@@ -262,10 +611,12 @@ impl Chip8 {
                         self.v[x] = self.v[x].wrapping_sub(self.v[y]);
                     },
                     
-                    // 8xy6 sets Vx = Vx >> 1, and stores least significant bit in Vf
+                    // 8xy6 sets Vx = Vx >> 1, and stores least significant bit in Vf.
+                    // With the shift_vy quirk, sets Vx = Vy >> 1 instead.
                     0x6 => {
-                        self.v[0xf] = self.v[x] & 0x1;
-                        self.v[x] = self.v[x].wrapping_shr(1);
+                        let value = if self.quirks.shift_vy { self.v[y] } else { self.v[x] };
+                        self.v[x] = value.wrapping_shr(1);
+                        self.v[0xf] = value & 0x1;
                     },
                     
                     // 8xy7 sets Vx = Vy - Vx, and sets Vf = not borrow
@@ -274,89 +625,112 @@ impl Chip8 {
                         self.v[x] = self.v[y].wrapping_sub(self.v[x]);
                     },
                     
-                    // 8xye sets Vx = Vx << 1, and stores most significant bit in Vf
+                    // 8xye sets Vx = Vx << 1, and stores most significant bit in Vf.
+                    // With the shift_vy quirk, sets Vx = Vy << 1 instead.
                     0xe => {
-                        self.v[0xf] = (self.v[x] >> 7) & 0x1;
-                        self.v[x] = self.v[x].wrapping_shl(1);
+                        let value = if self.quirks.shift_vy { self.v[y] } else { self.v[x] };
+                        self.v[x] = value.wrapping_shl(1);
+                        self.v[0xf] = (value >> 7) & 0x1;
                     },
                     
-                    _ => {
-                        unsupported_opcode(opcode, self.pc);
-                        return;
-                    },
+                    _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
                 }
             },
             
             // 9xy0 skips the next instruction of Vx != Vy
-            0x9 => if self.v[x] != self.v[y] { self.pc += 2 },
+            0x9 => if self.v[x] != self.v[y] { self.skip_next() },
             
             // annn sets i to the address at nnn
             0xa => self.i = nnn,
             
-            // bnnn jumps to address nnn + v0
-            0xb => self.pc = nnn.wrapping_add(self.v[0] as u16),
+            // bnnn jumps to address nnn + v0.
+            // With the jump_vx quirk, jumps to nnn + Vx instead.
+            0xb => {
+                let offset = if self.quirks.jump_vx { self.v[x] } else { self.v[0] };
+                self.pc = nnn.wrapping_add(offset as u16);
+            },
             
             // cxkk sets Vx to NN ANDed with a random byte
-            0xc => self.v[x] = nn & rand::thread_rng().gen_range(0x0, 0xff),
+            0xc => self.v[x] = nn & self.rng.next_u8(),
             
             // dxyn draws a sprite at location (Vx, Vy) of height N.
-            // The sprite is taken from memory address stored in register i
+            // The sprite is taken from memory address stored in register i.
+            // dxy0 draws a 16 x 16 SUPER-CHIP sprite instead, or 8 x 16 in
+            // low resolution with the lores_dxy0_8x16 quirk.
             0xd => {
-                self.render_flag = true;
-                for index in 0 .. n as usize {
-                    let sprite: u8 = self.mem[self.i as usize + index];
-                    
-                    let x = self.v[x] as usize;
-                    let y = self.v[y] as usize + index;
-                    
-                    let mut collision = false;
-
-                    for pixel_index in 0 .. 8 {
-                        let x = x + pixel_index;
-                        let pixel_index = 7 - pixel_index;
-                        let pixel = ((sprite >> pixel_index) & 0x1) == 0x1;
-                        if self.display[y % DISPLAY_HEIGHT][x % DISPLAY_WIDTH] && pixel {
-                            collision = true;
-                        }
-                        self.display[y % DISPLAY_HEIGHT][x % DISPLAY_WIDTH] ^= pixel;
+                // hold the instruction until the next vblank
+                if self.quirks.display_wait {
+                    if !self.vblank {
+                        self.pc = pc;
+                        return Ok(());
                     }
-                    
-                    self.v[0xf] = collision as u8;
+                    self.vblank = false;
+                }
+
+                // each selected bitplane takes its own copy of the sprite data,
+                // one after the other
+                let (width, len) = self.sprite_size(n);
+                let planes = self.plane.count_ones() as usize;
+                let sprite = self.mem_range(opcode, pc, self.i as usize, len * planes)?;
+                let (vx, vy) = (self.v[x] as usize, self.v[y] as usize);
+                self.render_flag = true;
+
+                let mut collision = false;
+                let mut start = sprite.start;
+                let selected = self.plane;
+                for plane in [0x1, 0x2].iter().filter(|&&plane| selected & plane != 0) {
+                    collision |= self.draw_sprite(vx, vy, width, *plane, start .. start + len);
+                    start += len;
                 }
+                self.v[0xf] = collision as u8;
             },
             
             0xe => {
                 match nn {
                     // ex9e skips the next instruction if the key of index Vx is pressed
-                    0x9e => if self.input[self.v[x] as usize] { self.pc += 2 },
+                    0x9e => if self.input[(self.v[x] & 0xf) as usize] { self.skip_next() },
                     
                     // exa1 skips the next instruction if the key of index Vx is not pressed
-                    0xa1 => if !self.input[self.v[x] as usize] { self.pc += 2 },
+                    0xa1 => if !self.input[(self.v[x] & 0xf) as usize] { self.skip_next() },
                     
-                    _ => {
-                        unsupported_opcode(opcode, self.pc);;
-                        return;
-                    },
+                    _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
                 }
             },
             
             0xf => {
                 match nn {
+                    // f000 nnnn sets the register i to the 16-bit address nnnn
+                    0x00 if x == 0 => {
+                        let address = self.mem_range(opcode, pc, self.pc as usize, 2)?.start;
+                        self.i = (self.mem[address] as u16) << 8 | self.mem[address + 1] as u16;
+                        self.pc = self.pc.wrapping_add(2);
+                    },
+
+                    // fn01 selects the bitplanes n to draw on
+                    0x01 => self.plane = x as u8 & 0x3,
+
+                    // f002 loads the 16-byte audio pattern from ram at location i
+                    0x02 if x == 0 => {
+                        let range = self.mem_range(opcode, pc, self.i as usize, 0x10)?;
+                        self.pattern.copy_from_slice(&self.mem[range]);
+                    },
+
                     // fx07 sets Vx to the value of the delay timer
                     0x07 => self.v[x] = self.dt,
                     
-                    // fx0a waits for a key to be pressed, and store its index in Vx
-                    0x0a => {
-                        let mut pressed = false;
-                        for index in 0 .. 0x10 {
-                            if self.input[index] {
-                                pressed = true;
-                                self.v[x] = index as u8;
-                            }
-                        }
-                        if !pressed {
-                            self.pc -= 2;
-                        }
+                    // fx0a waits for a key to be pressed and released, and
+                    // stores its index in Vx. Like the original interpreter,
+                    // keys already held when it starts don't count.
+                    0x0a => match self.key_wait {
+                        KeyWait::Released(key) => {
+                            self.v[x] = key;
+                            self.key_wait = KeyWait::Idle;
+                        },
+                        KeyWait::Idle => {
+                            self.key_wait = KeyWait::Waiting;
+                            self.pc = pc;
+                        },
+                        KeyWait::Waiting | KeyWait::Pressed(_) => self.pc = pc,
                     },
                     
                     // fx15 sets the delay timer to Vx
@@ -368,8 +742,14 @@ impl Chip8 {
                     // fx1e adds Vx to the address in register i
                     0x1e => self.i = self.i.wrapping_add(self.v[x] as u16),
                     
-                    // fx29 ets the register i to the address of sprite Vx
-                    0x29 => self.i = 5 * self.v[x] as u16,
+                    // fx29 sets the register i to the address of sprite Vx
+                    0x29 => self.i = 5 * (self.v[x] & 0xf) as u16,
+
+                    // fx30 sets the register i to the address of large sprite Vx
+                    0x30 => self.i = (BIG_FONT_ADDRESS + 10 * (self.v[x] & 0xf) as usize) as u16,
+
+                    // fx3a sets the audio pattern pitch to Vx
+                    0x3a => self.pitch = self.v[x],
                     
                     // fx33 stores the binary-coded decimal representation of Vx.
                     // Most significant 3 digits are stored at i.
@@ -377,68 +757,241 @@ impl Chip8 {
                     // Least significant digit is stored at i + 2.
                     0x33 => {
                         let vx = self.v[x];
-                        let i = self.i as usize;
+                        let i = self.mem_range(opcode, pc, self.i as usize, 3)?.start;
                         self.mem[i] = vx / 100;
                         self.mem[i + 1] = (vx / 10) % 10;
                         self.mem[i + 2] = vx % 10;
                     },
                     
                     // fx55 stores registers V0 - Vx into ram starting at location i.
+                    // With the load_store_increment_i quirk, i is left at i + x + 1.
                     0x55 => {
-                        for index in 0 .. x + 1 {
-                            self.mem[index + self.i as usize] = self.v[index];
+                        let range = self.mem_range(opcode, pc, self.i as usize, x + 1)?;
+                        self.mem[range].copy_from_slice(&self.v[..= x]);
+                        if self.quirks.load_store_increment_i {
+                            self.i = self.i.wrapping_add(x as u16 + 1);
                         }
                     },
                     
-                    // fx66 fills registers V0 - Vx with data in ram at location i.
+                    // fx65 fills registers V0 - Vx with data in ram at location i.
+                    // With the load_store_increment_i quirk, i is left at i + x + 1.
                     0x65 => {
-                        for index in 0 .. x + 1 {
-                            self.v[index] = self.mem[index + self.i as usize];
+                        let range = self.mem_range(opcode, pc, self.i as usize, x + 1)?;
+                        self.v[..= x].copy_from_slice(&self.mem[range]);
+                        if self.quirks.load_store_increment_i {
+                            self.i = self.i.wrapping_add(x as u16 + 1);
                         }
-                    }
-                    
-                    _ => {
-                        unsupported_opcode(opcode, self.pc);
-                        return;
                     },
+
+                    // fx75 stores registers V0 - Vx in the RPL user flags
+                    0x75 => self.rpl[..= x].copy_from_slice(&self.v[..= x]),
+
+                    // fx85 fills registers V0 - Vx from the RPL user flags
+                    0x85 => self.v[..= x].copy_from_slice(&self.rpl[..= x]),
+                    
+                    _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
                 }
             },
             
-            _  => {
-                unsupported_opcode(opcode, self.pc);;
-                return;
-            },
+            _ => return Err(EmulationError::UnknownOpcode { opcode, pc }),
         }
+
+        Ok(())
     }
     
     /// Get the value of the given register
     pub fn get_v(&self, register: usize) -> u8 {
         self.v[register]
     }
-    
+
+    /// Set the value of the given register
+    pub fn set_v(&mut self, register: usize, value: u8) {
+        self.v[register] = value;
+    }
+
     /// Get the value of the sound timer
     pub fn get_st(&self) -> u8 {
         self.st
     }
-    
+
+    /// Set the value of the sound timer
+    pub fn set_st(&mut self, value: u8) {
+        self.st = value;
+    }
+
     /// Get the value of the delay timer
     pub fn get_dt(&self) -> u8 {
         self.dt
     }
-    
+
+    /// Set the value of the delay timer
+    pub fn set_dt(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    /// Get the value of the index register
+    pub fn get_i(&self) -> u16 {
+        self.i
+    }
+
+    /// Set the value of the index register
+    pub fn set_i(&mut self, value: u16) {
+        self.i = value;
+    }
+
+    /// Get the address of the next instruction to execute
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    /// Set the address of the next instruction to execute
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value;
+    }
+
+    /// Get the number of return addresses on the stack
+    pub fn get_sp(&self) -> u8 {
+        self.sp
+    }
+
+    /// Get the return addresses on the stack, oldest first
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[.. self.sp as usize]
+    }
+
+    /// Get the XO-CHIP bitplanes that drawing, clearing and scrolling affect
+    pub fn get_plane(&self) -> u8 {
+        self.plane
+    }
+
+    /// Get the contents of RAM
+    pub fn get_mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Overwrite RAM with ``data``, starting at ``address``. Bytes that would
+    /// land past the end of RAM are dropped.
+    pub fn set_mem(&mut self, address: usize, data: &[u8]) {
+        let start = std::cmp::min(address, self.mem.len());
+        let end = std::cmp::min(start + data.len(), self.mem.len());
+        self.mem[start .. end].copy_from_slice(&data[.. end - start]);
+    }
+
     /// Check if the the pixel at the given (x, y) location is on or off
+    /// in any bitplane
     pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.display[y][x] != 0
+    }
+
+    /// Get the colour of the pixel at the given (x, y) location.
+    ///
+    /// Bit 0 holds the pixel of the first bitplane, and bit 1 the pixel of the
+    /// second, so the result is in the range 0 - 3.
+    pub fn get_pixel_color(&self, x: usize, y: usize) -> u8 {
         self.display[y][x]
     }
+
+    /// Get the 16-byte XO-CHIP audio pattern. Each bit is one sample, played
+    /// from the most significant bit of the first byte.
+    pub fn get_audio_pattern(&self) -> [u8; 0x10] {
+        self.pattern
+    }
+
+    /// Get the value of the XO-CHIP pitch register
+    pub fn get_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Get the rate in Hz that the bits of the audio pattern are played at
+    pub fn get_pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    /// Returns true if the display is in SUPER-CHIP high resolution mode
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Get the width of the display in the current resolution mode
+    pub fn display_width(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_WIDTH } else { DISPLAY_WIDTH }
+    }
+
+    /// Get the height of the display in the current resolution mode
+    pub fn display_height(&self) -> usize {
+        if self.hires { HIRES_DISPLAY_HEIGHT } else { DISPLAY_HEIGHT }
+    }
+
+    /// Returns true once the program has exited with the 00fd instruction
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Get the contents of the RPL user flags
+    pub fn get_rpl_flags(&self) -> [u8; 0x10] {
+        self.rpl
+    }
+
+    /// Set the contents of the RPL user flags
+    pub fn set_rpl_flags(&mut self, flags: [u8; 0x10]) {
+        self.rpl = flags;
+    }
+
+    /// Write the RPL user flags to a file, so they can be restored in a
+    /// later session with ``load_rpl_flags()``.
+    pub fn save_rpl_flags(&self, path: &str) -> std::io::Result<()> {
+        File::create(path)?.write_all(&self.rpl)
+    }
+
+    /// Read the RPL user flags from a file written by ``save_rpl_flags()``.
+    pub fn load_rpl_flags(&mut self, path: &str) -> std::io::Result<()> {
+        let mut flags = Vec::new();
+        File::open(path)?.take(0x10).read_to_end(&mut flags)?;
+        self.rpl[.. flags.len()].copy_from_slice(&flags);
+        Ok(())
+    }
     
     /// Check if the button of the given hex value is on or off
     pub fn get_input(&self, key: usize) -> bool {
         self.input[key]
     }
     
-    /// Set the value of a key on the keypad
+    /// Set the value of a key on the keypad.
+    ///
+    /// This is for frontends that read the state of the host keys, rather
+    /// than events. A change in value is passed on to ``key_down()`` or
+    /// ``key_up()``, so a press and release that both happen between two
+    /// calls are missed.
     pub fn set_input(&mut self, key: usize, value: bool) {
-      self.input[key] = value;
+        if value != self.input[key] {
+            if value {
+                self.key_down(key);
+            } else {
+                self.key_up(key);
+            }
+        }
+    }
+
+    /// Press a key on the keypad
+    pub fn key_down(&mut self, key: usize) {
+        self.input[key] = true;
+        if self.key_wait == KeyWait::Waiting {
+            self.key_wait = KeyWait::Pressed(key as u8);
+        }
+    }
+
+    /// Release a key on the keypad
+    pub fn key_up(&mut self, key: usize) {
+        self.input[key] = false;
+        if self.key_wait == KeyWait::Pressed(key as u8) {
+            self.key_wait = KeyWait::Released(key as u8);
+        }
+    }
+
+    /// Returns true while the program is blocked on fx0a, waiting for a key
+    /// to be pressed and released
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait != KeyWait::Idle
     }
     
     /// Returns true if the chip should be playing a sound
@@ -545,3 +1098,425 @@ impl std::fmt::Display for Chip8 {
              self.pc)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Assemble a program and load it into a new Chip8
+    fn chip(source: &str, quirks: Quirks) -> Chip8 {
+        let rom = asm::assemble(source).unwrap();
+        Chip8::with_rom_data(&rom, quirks).unwrap()
+    }
+
+    /// Execute ``instructions`` instructions, which must all succeed
+    fn run(chip: &mut Chip8, instructions: usize) {
+        for _ in 0 .. instructions {
+            chip.tick().unwrap();
+        }
+    }
+
+    /// Count the pixels that are lit in any bitplane
+    fn lit_pixels(chip: &Chip8) -> usize {
+        (0 .. chip.display_height())
+            .map(|y| (0 .. chip.display_width()).filter(|&x| chip.get_pixel(x, y)).count())
+            .sum()
+    }
+
+    /// Get the quirks of a preset with one of them changed
+    fn with(spec: &str) -> Quirks {
+        let mut quirks = Quirks::modern();
+        quirks.apply(spec).unwrap();
+        quirks
+    }
+
+    /// A program that waits for a key into V1, then loops at 0x204
+    fn key_wait() -> Chip8 {
+        chip("LD V1, #FF \n LD V1, K \n done: JP done", Quirks::default())
+    }
+
+    #[test]
+    fn key_wait_blocks_while_the_key_is_held() {
+        let mut chip = key_wait();
+        run(&mut chip, 2);
+        assert!(chip.is_waiting_for_key());
+
+        chip.key_down(0x5);
+        run(&mut chip, 10);
+        assert!(chip.is_waiting_for_key());
+        assert_eq!(chip.get_pc(), 0x202);
+        assert_eq!(chip.get_v(1), 0xff);
+    }
+
+    #[test]
+    fn key_wait_completes_when_the_key_is_released() {
+        let mut chip = key_wait();
+        run(&mut chip, 2);
+        chip.key_down(0xa);
+        run(&mut chip, 1);
+        chip.key_up(0xa);
+        assert_eq!(chip.get_pc(), 0x202);
+
+        run(&mut chip, 1);
+        assert!(!chip.is_waiting_for_key());
+        assert_eq!(chip.get_pc(), 0x204);
+        assert_eq!(chip.get_v(1), 0xa);
+    }
+
+    #[test]
+    fn key_wait_stores_the_key_that_was_released() {
+        let mut chip = key_wait();
+        run(&mut chip, 2);
+
+        // the first key pressed is the one waited on
+        chip.key_down(0x3);
+        chip.key_down(0x7);
+        chip.key_up(0x7);
+        run(&mut chip, 1);
+        assert!(chip.is_waiting_for_key());
+
+        chip.key_up(0x3);
+        run(&mut chip, 1);
+        assert_eq!(chip.get_v(1), 0x3);
+    }
+
+    #[test]
+    fn key_wait_ignores_keys_held_before_it_started() {
+        let mut chip = key_wait();
+        chip.key_down(0x4);
+        run(&mut chip, 2);
+        chip.key_up(0x4);
+        run(&mut chip, 5);
+        assert!(chip.is_waiting_for_key());
+        assert_eq!(chip.get_pc(), 0x202);
+
+        chip.key_down(0x6);
+        chip.key_up(0x6);
+        run(&mut chip, 1);
+        assert_eq!(chip.get_v(1), 0x6);
+    }
+
+    #[test]
+    fn key_wait_follows_the_level_of_set_input() {
+        let mut chip = key_wait();
+        run(&mut chip, 2);
+
+        chip.set_input(0x2, true);
+        run(&mut chip, 1);
+        chip.set_input(0x2, true);
+        run(&mut chip, 1);
+        assert!(chip.is_waiting_for_key());
+
+        chip.set_input(0x2, false);
+        run(&mut chip, 1);
+        assert!(!chip.is_waiting_for_key());
+        assert_eq!(chip.get_v(1), 0x2);
+    }
+
+    #[test]
+    fn unknown_opcodes_are_skipped() {
+        let mut chip = chip("DW #5001 \n LD V0, 1", Quirks::default());
+        let err = chip.tick().unwrap_err();
+        assert_eq!(err, EmulationError::UnknownOpcode { opcode: 0x5001, pc: 0x200 });
+        assert_eq!((err.pc(), err.opcode()), (0x200, Some(0x5001)));
+        assert_eq!(chip.get_pc(), 0x202);
+
+        run(&mut chip, 1);
+        assert_eq!(chip.get_v(0), 1);
+    }
+
+    #[test]
+    fn calls_past_the_top_of_the_stack_overflow() {
+        let mut chip = chip("loop: CALL loop", Quirks::default());
+        run(&mut chip, 16);
+        assert_eq!(chip.tick(), Err(EmulationError::StackOverflow { opcode: 0x2200, pc: 0x200 }));
+        assert_eq!(chip.get_sp(), 16);
+        assert_eq!(chip.get_stack(), &[0x202; 16]);
+        assert_eq!(chip.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn returns_from_an_empty_stack_underflow() {
+        let mut chip = chip("RET", Quirks::default());
+        assert_eq!(chip.tick(), Err(EmulationError::StackUnderflow { opcode: 0x00ee, pc: 0x200 }));
+        assert_eq!(chip.get_sp(), 0);
+        assert_eq!(chip.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn memory_accesses_past_the_end_of_ram_fail() {
+        let source = "LD I, long #FFFE \n LD V2, 7 \n LD [I], V2 \n LD V2, [I] \n LD B, V2";
+        let mut chip = chip(source, Quirks::xochip());
+        run(&mut chip, 2);
+
+        let err = EmulationError::MemoryOutOfBounds { opcode: 0xf255, pc: 0x206, address: MEMORY_SIZE };
+        assert_eq!(chip.tick(), Err(err));
+        assert_eq!(&chip.get_mem()[0xfffe ..], &[0, 0]);
+        assert_eq!(chip.get_i(), 0xfffe);
+
+        assert!(chip.tick().is_err());
+        assert_eq!(chip.get_v(2), 7);
+
+        chip.set_i(0xffff);
+        assert_eq!(chip.tick(), Err(EmulationError::MemoryOutOfBounds { opcode: 0xf233, pc: 0x20a, address: MEMORY_SIZE }));
+        assert_eq!(chip.get_mem()[0xffff], 0);
+    }
+
+    #[test]
+    fn an_out_of_bounds_pc_is_left_alone() {
+        let mut chip = chip("", Quirks::default());
+        chip.set_pc(0xffff);
+        let err = chip.tick().unwrap_err();
+        assert_eq!(err, EmulationError::PcOutOfBounds { pc: 0xffff });
+        assert_eq!((err.pc(), err.opcode()), (0xffff, None));
+        assert_eq!(chip.get_pc(), 0xffff);
+        assert_eq!(chip.tick(), Err(err));
+
+        // the last two bytes of memory still hold an instruction
+        chip.set_mem(0xfffe, &[0x60, 0x42]);
+        chip.set_pc(0xfffe);
+        run(&mut chip, 1);
+        assert_eq!(chip.get_v(0), 0x42);
+    }
+
+    #[test]
+    fn shift_vy_shifts_vy_into_vx() {
+        let source = "LD V1, #81 \n LD V2, #06 \n SHR V1, V2 \n LD V3, #41 \n LD V4, #C0 \n SHL V3, V4";
+        for &(quirks, right, left) in [(with("shift-vy"), 0x03, 0x80), (Quirks::modern(), 0x40, 0x82)].iter() {
+            let mut chip = chip(source, quirks);
+            run(&mut chip, 3);
+            assert_eq!(chip.get_v(1), right);
+            assert_eq!(chip.get_v(0xf), if quirks.shift_vy { 0 } else { 1 });
+            run(&mut chip, 3);
+            assert_eq!(chip.get_v(3), left);
+            assert_eq!(chip.get_v(0xf), if quirks.shift_vy { 1 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn load_store_increment_i_moves_i_past_the_registers() {
+        let source = "LD I, #300 \n LD [I], V2 \n LD V3, [I]";
+        for &(quirks, after_store, after_load) in [(with("load-store-increment-i"), 0x303, 0x307),
+                                                   (Quirks::modern(), 0x300, 0x300)].iter() {
+            let mut chip = chip(source, quirks);
+            run(&mut chip, 2);
+            assert_eq!(chip.get_i(), after_store);
+            run(&mut chip, 1);
+            assert_eq!(chip.get_i(), after_load);
+        }
+    }
+
+    #[test]
+    fn jump_vx_adds_the_register_named_by_the_address() {
+        let source = "LD V0, 4 \n LD V3, 8 \n JP V0, #310";
+        for &(quirks, target) in [(with("jump-vx"), 0x318), (Quirks::modern(), 0x314)].iter() {
+            let mut chip = chip(source, quirks);
+            run(&mut chip, 3);
+            assert_eq!(chip.get_pc(), target);
+        }
+    }
+
+    #[test]
+    fn vf_reset_clears_vf_after_logic_operations() {
+        for op in ["OR", "AND", "XOR"].iter() {
+            let source = format!("LD VF, 1 \n LD V0, 3 \n LD V1, 5 \n {} V0, V1", op);
+            for &(quirks, vf) in [(with("vf-reset"), 0), (Quirks::modern(), 1)].iter() {
+                let mut chip = chip(&source, quirks);
+                run(&mut chip, 4);
+                assert_eq!(chip.get_v(0xf), vf, "{}", op);
+            }
+        }
+    }
+
+    #[test]
+    fn clip_sprites_cuts_off_sprites_at_the_edges() {
+        let source = "LD V0, 62 \n LD V1, 30 \n LD I, solid \n DRW V0, V1, 4 \n solid: DB #FF, #FF, #FF, #FF";
+        for &(quirks, lit, corner) in [(with("clip-sprites"), 4, false), (Quirks::modern(), 32, true)].iter() {
+            let mut chip = chip(source, quirks);
+            run(&mut chip, 4);
+            assert_eq!(lit_pixels(&chip), lit);
+            assert_eq!(chip.get_pixel(0, 0), corner);
+            assert!(chip.get_pixel(63, 31));
+        }
+    }
+
+    #[test]
+    fn display_wait_holds_sprites_until_the_vertical_blank() {
+        let mut chip = chip("DRW V0, V0, 5 \n DRW V0, V0, 5", with("display-wait"));
+        run(&mut chip, 3);
+        assert_eq!(chip.get_pc(), 0x200);
+        assert_eq!(lit_pixels(&chip), 0);
+
+        chip.tick_timers();
+        run(&mut chip, 2);
+        assert_eq!(chip.get_pc(), 0x202);
+        assert_eq!(lit_pixels(&chip), 14);
+
+        let mut chip = self::chip("DRW V0, V0, 5 \n DRW V0, V0, 5", Quirks::modern());
+        run(&mut chip, 1);
+        assert_eq!(chip.get_pc(), 0x202);
+    }
+
+    #[test]
+    fn high_and_low_switch_resolution_and_clear_the_display() {
+        let mut chip = chip("DRW V0, V0, 5 \n HIGH \n DRW V0, V0, 5 \n LOW", Quirks::schip());
+        run(&mut chip, 2);
+        assert!(chip.is_hires());
+        assert_eq!((chip.display_width(), chip.display_height()), (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT));
+        assert_eq!(lit_pixels(&chip), 0);
+
+        run(&mut chip, 2);
+        assert!(!chip.is_hires());
+        assert_eq!((chip.display_width(), chip.display_height()), (DISPLAY_WIDTH, DISPLAY_HEIGHT));
+        assert_eq!(lit_pixels(&chip), 0);
+    }
+
+    #[test]
+    fn scrolls_the_display() {
+        // a single pixel at (10, 10)
+        let source = "LD V0, 10 \n LD I, dot \n DRW V0, V0, 1 \n SCD 3 \n SCR \n SCL \n SCL \n SCU 2 \n dot: DB #80";
+        let mut chip = chip(source, Quirks::schip());
+        run(&mut chip, 3);
+        assert!(chip.get_pixel(10, 10));
+
+        let expected = [(10, 13), (14, 13), (10, 13), (6, 13), (6, 11)];
+        for &(x, y) in expected.iter() {
+            run(&mut chip, 1);
+            assert!(chip.get_pixel(x, y), "({}, {})", x, y);
+            assert_eq!(lit_pixels(&chip), 1);
+        }
+
+        // pixels scrolled off the edge are gone
+        let mut chip = self::chip("LD I, dot \n DRW V0, V0, 1 \n SCL \n SCR \n dot: DB #80", Quirks::schip());
+        run(&mut chip, 4);
+        assert_eq!(lit_pixels(&chip), 0);
+    }
+
+    #[test]
+    fn exit_stops_at_the_exit_instruction() {
+        let mut chip = chip("EXIT", Quirks::schip());
+        run(&mut chip, 2);
+        assert!(chip.has_exited());
+        assert_eq!(chip.get_pc(), 0x200);
+    }
+
+    #[test]
+    fn dxy0_draws_16_by_16_sprites() {
+        let source = "HIGH \n LD I, #300 \n DRW V0, V0, 0";
+        let mut chip = chip(source, Quirks::schip());
+        chip.set_mem(0x300, &[0xff; 32]);
+        run(&mut chip, 3);
+        assert_eq!(lit_pixels(&chip), 256);
+        assert!(chip.get_pixel(15, 15));
+    }
+
+    #[test]
+    fn lores_dxy0_8x16_draws_narrow_sprites_in_low_resolution() {
+        let source = "LD I, #300 \n DRW V0, V0, 0";
+        for &(quirks, lit) in [(Quirks::schip(), 128), (Quirks::modern(), 256)].iter() {
+            let mut chip = chip(source, quirks);
+            chip.set_mem(0x300, &[0xff; 32]);
+            run(&mut chip, 2);
+            assert_eq!(lit_pixels(&chip), lit);
+            assert!(chip.get_pixel(7, 15));
+            assert_eq!(chip.get_pixel(8, 0), !quirks.lores_dxy0_8x16);
+        }
+    }
+
+    #[test]
+    fn fx30_points_i_at_the_big_font() {
+        let mut chip = chip("LD V0, 7 \n LD HF, V0", Quirks::schip());
+        run(&mut chip, 2);
+        let i = chip.get_i() as usize;
+        assert_eq!(i, BIG_FONT_ADDRESS + 70);
+        assert_eq!(&chip.get_mem()[i .. i + 10], &BIG_FONT[70 .. 80]);
+    }
+
+    #[test]
+    fn fx75_and_fx85_save_and_restore_the_rpl_flags() {
+        let source = "LD V0, 1 \n LD V1, 2 \n LD V2, 3 \n LD R, V1 \n LD V0, 0 \n LD V1, 0 \n LD V2, 0 \n LD V2, R";
+        let mut chip = chip(source, Quirks::schip());
+        run(&mut chip, 4);
+        assert_eq!(&chip.get_rpl_flags()[.. 3], &[1, 2, 0]);
+
+        run(&mut chip, 4);
+        assert_eq!((chip.get_v(0), chip.get_v(1), chip.get_v(2)), (1, 2, 0));
+    }
+
+    #[test]
+    fn register_ranges_are_stored_and_loaded_in_either_order() {
+        let source = "LD V1, 1 \n LD V2, 2 \n LD V3, 3 \n LD I, #300 \n LD [I], V1-V3 \n LD I, #310 \n LD [I], V3-V1";
+        let mut chip = chip(source, Quirks::xochip());
+        run(&mut chip, 7);
+        assert_eq!(&chip.get_mem()[0x300 .. 0x304], &[1, 2, 3, 0]);
+        assert_eq!(&chip.get_mem()[0x310 .. 0x314], &[3, 2, 1, 0]);
+        assert_eq!(chip.get_i(), 0x310);
+
+        let source = "LD I, #300 \n LD V4-V6, [I] \n LD V9-V7, [I]";
+        let mut chip = self::chip(source, Quirks::xochip());
+        chip.set_mem(0x300, &[4, 5, 6]);
+        run(&mut chip, 3);
+        assert_eq!((chip.get_v(4), chip.get_v(5), chip.get_v(6)), (4, 5, 6));
+        assert_eq!((chip.get_v(7), chip.get_v(8), chip.get_v(9)), (6, 5, 4));
+        assert_eq!(chip.get_i(), 0x300);
+    }
+
+    #[test]
+    fn long_loads_reach_all_of_memory_and_are_skipped_whole() {
+        let source = "LD I, long #ABCD \n SE V0, 0 \n LD I, long #1234 \n LD V1, 1";
+        let mut chip = chip(source, Quirks::xochip());
+        run(&mut chip, 1);
+        assert_eq!(chip.get_i(), 0xabcd);
+        assert_eq!(chip.get_pc(), 0x204);
+
+        run(&mut chip, 2);
+        assert_eq!(chip.get_i(), 0xabcd);
+        assert_eq!(chip.get_v(1), 1);
+    }
+
+    #[test]
+    fn roms_can_fill_all_of_memory() {
+        let mut rom = vec![0; MAX_ROM_SIZE];
+        rom[MAX_ROM_SIZE - 1] = 0x42;
+        let chip = Chip8::with_rom_data(&rom, Quirks::xochip()).unwrap();
+        assert_eq!(chip.get_mem()[MEMORY_SIZE - 1], 0x42);
+
+        rom.push(0);
+        assert!(Chip8::with_rom_data(&rom, Quirks::xochip()).is_err());
+    }
+
+    #[test]
+    fn plane_selects_the_bitplanes_that_are_drawn_and_cleared() {
+        // the second plane, then both planes with a sprite for each
+        let source = "LD I, #300 \n PLANE 2 \n DRW V0, V0, 1 \n PLANE 3 \n LD V0, 8 \n DRW V0, V0, 1 \n PLANE 1 \n CLS";
+        let mut chip = chip(source, Quirks::xochip());
+        chip.set_mem(0x300, &[0x80, 0xc0]);
+        run(&mut chip, 3);
+        assert_eq!(chip.get_plane(), 2);
+        assert_eq!(chip.get_pixel_color(0, 0), 2);
+
+        run(&mut chip, 3);
+        assert_eq!(chip.get_pixel_color(8, 8), 3);
+        assert_eq!(chip.get_pixel_color(9, 8), 2);
+
+        run(&mut chip, 2);
+        assert_eq!(chip.get_pixel_color(0, 0), 2);
+        assert_eq!(chip.get_pixel_color(8, 8), 2);
+        assert_eq!(chip.get_pixel_color(9, 8), 2);
+    }
+
+    #[test]
+    fn audio_loads_the_pattern_and_pitch_sets_its_rate() {
+        let mut chip = chip("LD I, #300 \n AUDIO \n LD V0, 112 \n LD PITCH, V0", Quirks::xochip());
+        let pattern: Vec<u8> = (0 .. 16).collect();
+        chip.set_mem(0x300, &pattern);
+        assert_eq!(chip.get_pattern_rate(), 4000.0);
+
+        run(&mut chip, 2);
+        assert_eq!(&chip.get_audio_pattern()[..], &pattern[..]);
+
+        run(&mut chip, 2);
+        assert_eq!(chip.get_pitch(), 112);
+        assert_eq!(chip.get_pattern_rate(), 8000.0);
+    }
+}
@@ -1,110 +1,292 @@
 extern crate sdl2;
-use sdl2::keyboard::Scancode;
-use sdl2::event::Event;
-use sdl2::pixels::Color;
+use sdl2::keyboard::{ Scancode, LSHIFTMOD, RSHIFTMOD };
 
 extern crate chip8;
-use chip8::{ Chip8, DISPLAY_WIDTH, DISPLAY_HEIGHT };
+use chip8::{ Chip8, EmulationError };
+use chip8::debugger::StopReason;
+use chip8::frontend::Machine;
+use chip8::rewind::Rewind;
 
 mod screen;
-use screen::Screen;
+use screen::{ Scaling, Screen };
 
 mod buzzer;
 use buzzer::Buzzer;
 
-use std::time::{Duration, Instant};
+mod keyboard;
+use keyboard::Keyboard;
+
+mod keymap;
+
+mod controller;
+use controller::Controllers;
+
+mod options;
+use options::Command;
+
+mod console;
+
+use std::fs::File;
+use std::io::{ Read, Write };
+use std::path::Path;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: {} <path to rom file>", args.get(0).unwrap());
-        return;
+    
+    let options = match options::parse_args(&args[1 ..]) {
+        Ok(Command::Run(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", options::USAGE.replace("{}", &args[0]));
+            return;
+        },
+        Err(err) => {
+            eprintln!("{}: {}", args[0], err);
+            eprintln!("Run '{} --help' for the list of options", args[0]);
+            std::process::exit(1);
+        },
+    };
+    let rom_file = options.rom_file;
+    let palettes = options.palettes;
+    let mut palette_index = options.palette_index;
+    
+    // setup chip-8 emulator structure
+    let mut chip = match Chip8::from_rom_file(&rom_file, options.quirks) {
+        Ok(chip) => chip,
+        Err(err) => {
+            eprintln!("Could not load {}: {}", rom_file, err);
+            std::process::exit(1);
+        },
+    };
+    if let Some(seed) = options.seed {
+        chip.seed_rng(seed);
     }
     
     // setup SDL2 subsystems
     let sdl_ctx = sdl2::init().unwrap();
     let vid_ctx = sdl_ctx.video().unwrap();
     let audio_ctx = sdl_ctx.audio().unwrap();
-    let mut event_pump = sdl_ctx.event_pump().unwrap();
-    
-    // clock speed for the execution of chip-8 commands in MHz
-    let clock_speed = 1;
-    let clock_speed = (1 / clock_speed) * 1000000;
-    let clock_speed = Duration::new(0, clock_speed);
-    let mut last_cycle = Instant::now();
+    let controller_ctx = sdl_ctx.game_controller().unwrap();
+    let event_pump = sdl_ctx.event_pump().unwrap();
     
-    // colors for pixels that are 'on' and 'off'
-    let on_color = Color::RGB(0xff, 0xff, 0xff);
-    let off_color = Color::RGB(0x00, 0x00, 0x00);
+    // the emulator runs in 60Hz frames, executing a fixed number of
+    // chip-8 commands in each one before ticking the timers
+    let cycles_per_frame = options.cycles_per_frame;
     
-    // setup window to render graphics into
-    let mut window = Screen::new(&vid_ctx);
-    window.set_scale(10, 10);
+    // setup window to render graphics into. The display's texture borrows
+    // the creator it came from, so the creator outlives the screen.
+    let canvas = screen::open_window(&vid_ctx);
+    let texture_creator = canvas.texture_creator();
+    let mut window = Screen::new(canvas, &texture_creator);
+    window.set_scale(options.scale, options.scale);
+    window.set_fullscreen(options.fullscreen);
+    window.set_palette(palettes[palette_index].1);
+    window.set_filter(options.filter);
     
     // setup buzzer to play sounds
-    let buzzer = Buzzer::new(&audio_ctx);
+    let mut buzzer = Buzzer::new(&audio_ctx, options.volume);
+    buzzer.set_muted(options.muted);
+    buzzer.synth_mut().set_tone(options.tone);
+    buzzer.synth_mut().set_low_pass(options.low_pass);
     
-    // setup chip-8 emulator structure
-    let mut chip = Chip8::from_rom_file(&args.get(1).unwrap()).unwrap();
+    // restore the SUPER-CHIP user flags saved by a previous session, if any
+    let rpl_file = format!("{}.rpl", rom_file);
+    let _ = chip.load_rpl_flags(&rpl_file);
+    let saved_rpl_flags = chip.get_rpl_flags();
     
-    'mainloop: loop {
-        let dt = last_cycle.elapsed();
+    // the ROM's own keymap, if any, overrides the one given on the command
+    // line, since many games use only a few keys scattered over the keypad
+    let mut keymap = options.keymap;
+    let keymap_file = format!("{}.keymap", rom_file);
+    if Path::new(&keymap_file).exists() {
+        if let Err(err) = keymap.load(&keymap_file) {
+            println!("[WARNING] {}", err);
+        }
+    }
     
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit{ .. } => break 'mainloop,
-                _ => (),
-            }
+    // likewise for game controllers, where a two player game might want
+    // each controller on a different set of keys
+    let mut controller_profile = options.controller_profile;
+    let controller_file = format!("{}.controller", rom_file);
+    if Path::new(&controller_file).exists() {
+        if let Err(err) = controller_profile.load(&controller_file) {
+            println!("[WARNING] {}", err);
         }
-        
-        // run the emulator at at the given clock speed
-        if dt >= clock_speed {
-            last_cycle = Instant::now();
+    }
+    let controllers = Controllers::new(controller_ctx, controller_profile);
+    
+    let keyboard = Keyboard::new(event_pump, controllers, keymap);
+    let mut machine = Machine::new(chip, window, buzzer, keyboard, cycles_per_frame);
+    
+    // keep a snapshot of every frame for the last 30 seconds, which can be
+    // played backwards by holding backspace
+    let mut rewind = Rewind::new(30 * 60);
+    
+    // F12 breaks into the debugger console on stdin, as does hitting a
+    // breakpoint set from it
+    let mut paused = options.pause_on_start;
+    
+    'mainloop: while machine.poll_input() {
+        for (scancode, keymod) in machine.input_mut().take_hotkeys() {
+            // F12 pauses, and shift + F12 rebinds the keypad
+            if scancode == Scancode::F12 {
+                if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                    machine.input_mut().start_binding();
+                } else {
+                    paused = true;
+                }
+            }
             
-            // update the state of each key of the emulator's keyboard
-            update_keypad(&mut chip, &event_pump);
+            // F11 switches between whole number and aspect correct scaling
+            if scancode == Scancode::F11 {
+                let screen = machine.display_mut();
+                let scaling = match screen.scaling() {
+                    Scaling::Integer => Scaling::Aspect,
+                    Scaling::Aspect => Scaling::Integer,
+                };
+                screen.set_scaling(scaling);
+                machine.draw();
+            }
             
-            // run one clock cycle
-            chip.tick();  
+            // F10 cycles through the palettes
+            if scancode == Scancode::F10 {
+                palette_index = (palette_index + 1) % palettes.len();
+                println!("Palette: {}", palettes[palette_index].0);
+                machine.display_mut().set_palette(palettes[palette_index].1);
+                machine.draw();
+            }
             
-            // update the host's buzzer with the state of the chip's sound timer
-            buzzer.set(chip.sound_status());
-
-            if chip.get_render_flag() {
-                // update host's window with chip's graphics
-                for y in 0 .. DISPLAY_HEIGHT {
-                    for x in 0 .. DISPLAY_WIDTH {
-                        let color =  if chip.get_pixel(x, y) {
-                            on_color
-                        } else {
-                            off_color
-                        };
-                        window.set_pixel(color, x as i32, y as i32);
+            // ctrl + M mutes and unmutes the sound
+            if scancode == Scancode::M {
+                let buzzer = machine.audio_mut();
+                let muted = !buzzer.is_muted();
+                buzzer.set_muted(muted);
+                println!("Sound {}", if muted { "off" } else { "on" });
+            }
+            
+            // F1 - F9 load a save slot, and shift + F1 - F9 save to it
+            if let Some(slot) = save_slot(scancode) {
+                let state_file = format!("{}.state{}", rom_file, slot);
+                if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                    match save_state(machine.chip(), &state_file) {
+                        Ok(()) => println!("Saved state to slot {}", slot),
+                        Err(err) => println!("[WARNING] could not save {}: {}", state_file, err),
+                    }
+                } else {
+                    // the snapshot brings its own quirks with it
+                    let quirks = machine.chip().get_quirks();
+                    match load_state(machine.chip_mut(), &state_file) {
+                        Ok(()) => {
+                            println!("Loaded state from slot {}", slot);
+                            if machine.chip().get_quirks() != quirks {
+                                println!("[WARNING] slot {} was saved with different quirks, which are now in use", slot);
+                            }
+                            machine.draw();
+                        },
+                        Err(err) => println!("[WARNING] could not load {}: {}", state_file, err),
                     }
                 }
-
-                window.update();
+            }
+        }
+        
+        if machine.input_mut().take_rebound() {
+            match machine.input().keymap().save(&keymap_file) {
+                Ok(()) => println!("Saved keymap to {}", keymap_file),
+                Err(err) => println!("[WARNING] could not save {}: {}", keymap_file, err),
+            }
+        }
+        
+        // the program has executed the SUPER-CHIP exit instruction
+        if machine.chip().has_exited() {
+            break 'mainloop;
+        }
+        
+        if paused {
+            paused = false;
+            machine.silence();
+            let (chip, debugger) = machine.chip_and_debugger_mut();
+            if !console::run(chip, debugger) {
+                break 'mainloop;
+            }
+            machine.reset_clock();
+        }
+        
+        // wait until it is time for the next frame
+        machine.wait_for_frame();
+        
+        // hold the game still while its keys are being bound
+        if machine.input().is_binding() {
+            machine.silence();
+            continue;
+        }
+        
+        if machine.input().is_pressed(Scancode::Backspace) {
+            // step back one frame, staying on the oldest one once the
+            // history runs out
+            if let Some(state) = rewind.pop() {
+                machine.chip_mut().load_state(state).unwrap();
+            }
+            machine.draw();
+            machine.silence();
+        } else {
+            // run one frame worth of clock cycles
+            let waiting = machine.chip().is_waiting_for_key();
+            match machine.run_frame() {
+                Ok(()) => rewind.push(machine.chip().save_state()),
+                // the program counter can't move on from outside of memory,
+                // so there's no point carrying on
+                Err(StopReason::Error(err @ EmulationError::PcOutOfBounds { .. })) => {
+                    println!("[WARNING] {}", err);
+                    paused = true;
+                },
+                Err(StopReason::Error(err)) => println!("[WARNING] {}", err),
+                Err(reason) => {
+                    println!("{}", reason);
+                    machine.draw();
+                    paused = true;
+                },
+            }
+            
+            // the title shows when the program is waiting for a key
+            if machine.chip().is_waiting_for_key() != waiting {
+                machine.draw();
             }
         }
     }
+    
+    let chip = machine.chip();
+    if chip.get_rpl_flags() != saved_rpl_flags {
+        if let Err(err) = chip.save_rpl_flags(&rpl_file) {
+            println!("[WARNING] could not save RPL user flags to {}: {}", rpl_file, err);
+        }
+    }
+}
+
+/// Get the save slot bound to the given key, if any
+fn save_slot(scancode: Scancode) -> Option<u32> {
+    match scancode {
+        Scancode::F1 => Some(1),
+        Scancode::F2 => Some(2),
+        Scancode::F3 => Some(3),
+        Scancode::F4 => Some(4),
+        Scancode::F5 => Some(5),
+        Scancode::F6 => Some(6),
+        Scancode::F7 => Some(7),
+        Scancode::F8 => Some(8),
+        Scancode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+/// Write a snapshot of the emulator to the given file
+fn save_state(chip: &Chip8, path: &str) -> std::io::Result<()> {
+    File::create(path)?.write_all(&chip.save_state())
 }
 
-fn update_keypad(chip: &mut Chip8, event_pump: &sdl2::EventPump) {
-    let keyboard_state = event_pump.keyboard_state();
-    chip.set_input(0x1, keyboard_state.is_scancode_pressed(Scancode::Num1));
-    chip.set_input(0x2, keyboard_state.is_scancode_pressed(Scancode::Num2));
-    chip.set_input(0x3, keyboard_state.is_scancode_pressed(Scancode::Num3));
-    chip.set_input(0xC, keyboard_state.is_scancode_pressed(Scancode::Num4));
-    chip.set_input(0x4, keyboard_state.is_scancode_pressed(Scancode::Q));
-    chip.set_input(0x5, keyboard_state.is_scancode_pressed(Scancode::W));
-    chip.set_input(0x6, keyboard_state.is_scancode_pressed(Scancode::E));
-    chip.set_input(0xD, keyboard_state.is_scancode_pressed(Scancode::R));
-    chip.set_input(0x7, keyboard_state.is_scancode_pressed(Scancode::A));
-    chip.set_input(0x8, keyboard_state.is_scancode_pressed(Scancode::S));
-    chip.set_input(0x9, keyboard_state.is_scancode_pressed(Scancode::D));
-    chip.set_input(0xE, keyboard_state.is_scancode_pressed(Scancode::F));
-    chip.set_input(0xA, keyboard_state.is_scancode_pressed(Scancode::Z));
-    chip.set_input(0x0, keyboard_state.is_scancode_pressed(Scancode::X));
-    chip.set_input(0xB, keyboard_state.is_scancode_pressed(Scancode::C));
-    chip.set_input(0xF, keyboard_state.is_scancode_pressed(Scancode::V));
+/// Restore the emulator from a snapshot in the given file
+fn load_state(chip: &mut Chip8, path: &str) -> std::io::Result<()> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    chip.load_state(&data).map_err(|err| {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    })
 }
@@ -0,0 +1,234 @@
+extern crate chip8;
+
+use chip8::Quirks;
+use chip8::audio::{ Tone, Waveform };
+use chip8::filter::FilterMode;
+use chip8::palette::{ self, Palette, PRESETS };
+
+use controller::ControllerProfile;
+use keymap::Keymap;
+
+use std::slice::Iter;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+Usage: {} [options] <path to rom file>
+
+Options:
+  --mode <name>                 the platform the ROM was written for, which
+                                picks its quirks: chip8 (or vip), schip,
+                                xochip or modern (the default)
+  --quirks <list>               quirks to change, separated by commas: a
+                                preset (chip8, schip, xochip, modern) or
+                                shift-vy, load-store-increment-i, jump-vx,
+                                vf-reset, clip-sprites, display-wait or
+                                lores-dxy0-8x16, prefixed with no- to turn
+                                them off
+  --ips <n>                     instructions executed per second
+  --cycles-per-frame <n>        instructions executed per 60Hz frame
+                                (default 15)
+  --seed <n>                    seed the random number generator
+  --scale <n>                   size of a display pixel in the window
+                                (default 10)
+  --fullscreen                  fill the whole screen
+  --palette <name|colours>      a preset (classic, green, amber, lcd,
+                                high-contrast, octo), or 2 or 4 colours
+                                like #000000,#33ff66
+  --palette-file <file>         add the palettes in a file, one
+                                '<name> = <colours>' per line
+  --filter <mode>               none, decay[:<half life>],
+                                blend[:<frames>] or last2 to smooth flicker
+  --keymap <file>               bind host keys to the keypad, one
+                                '<hex key> = <host keys>' per line.
+                                <rom>.keymap is applied on top if it exists
+  --controller <file>           bind game controller buttons to the keypad,
+                                one '<hex key> = <buttons>' per line.
+                                <rom>.controller is applied on top if it
+                                exists
+  --mute                        start with the sound off
+  --volume <0-100>              loudness of the buzzer (default 25)
+  --waveform <shape>            the buzzer's tone for ROMs without an
+                                XO-CHIP audio pattern: square,
+                                pulse[:<duty>], triangle, sine or noise
+                                (default square)
+  --frequency <Hz>              pitch of the buzzer's tone (default 440)
+  --low-pass <Hz>               soften the sound by cutting off frequencies
+                                above this, around 2000 sounds like the
+                                VIP's speaker
+  --pause-on-start              open the debugger console before running
+  -h, --help                    show this help
+
+Keys:
+  F1 - F9          load a save slot, with shift to save to it
+  F10              cycle through the palettes
+  F11              switch between whole number and aspect scaling
+  Ctrl + M         turn the sound off and on
+  F12              pause and open the debugger console
+  Shift + F12      bind the keypad keys one at a time, saving the
+                   keymap to <rom>.keymap
+  Backspace        rewind";
+
+/// The options given on the command line
+pub struct Options {
+    pub rom_file: String,
+    pub quirks: Quirks,
+    pub cycles_per_frame: usize,
+    pub seed: Option<u64>,
+    pub scale: u32,
+    pub fullscreen: bool,
+
+    /// The palettes F10 cycles through, and the one to start with
+    pub palettes: Vec<(String, Palette)>,
+    pub palette_index: usize,
+
+    pub filter: FilterMode,
+    pub keymap: Keymap,
+    pub controller_profile: ControllerProfile,
+    pub muted: bool,
+
+    /// The volume of the buzzer, from 0 to 1
+    pub volume: f32,
+
+    pub tone: Tone,
+    pub low_pass: Option<f32>,
+
+    pub pause_on_start: bool,
+}
+
+/// What the command line asks for
+pub enum Command {
+    Run(Options),
+    Help,
+}
+
+/// Parse the command line arguments, not including the program name.
+/// Returns a message explaining what is wrong if they aren't valid.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let mut options = Options {
+        rom_file: String::new(),
+        quirks: Quirks::default(),
+        cycles_per_frame: 15,
+        seed: None,
+        scale: 10,
+        fullscreen: false,
+        palettes: PRESETS.iter().map(|&(name, palette)| (name.to_string(), palette)).collect(),
+        palette_index: 0,
+        filter: FilterMode::None,
+        keymap: Keymap::default(),
+        controller_profile: ControllerProfile::default(),
+        muted: false,
+        volume: 0.25,
+        tone: Tone::default(),
+        low_pass: None,
+        pause_on_start: false,
+    };
+
+    // quirks are applied on top of the mode, in whatever order they came
+    let mut quirks = Vec::new();
+    let mut only_files = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if only_files || !arg.starts_with('-') {
+            if !options.rom_file.is_empty() {
+                return Err(format!("unexpected argument '{}', only one ROM can be run", arg));
+            }
+            options.rom_file = arg.clone();
+            continue;
+        }
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--" => only_files = true,
+            "--mode" => {
+                let mode = value(&mut args, arg)?;
+                options.quirks = Quirks::preset(mode).ok_or_else(|| {
+                    format!("unknown mode '{}', expected one of {}", mode, Quirks::PRESETS.join(", "))
+                })?;
+            },
+            "--quirks" => quirks.push(value(&mut args, arg)?),
+            "--ips" => {
+                let ips: usize = positive(&mut args, arg)?;
+                options.cycles_per_frame = ((ips + 30) / 60).max(1);
+            },
+            "--cycles-per-frame" => options.cycles_per_frame = positive(&mut args, arg)?,
+            "--seed" => options.seed = Some(number(&mut args, arg)?),
+            "--scale" => options.scale = positive(&mut args, arg)?,
+            "--fullscreen" => options.fullscreen = true,
+            "--palette" => {
+                let spec = value(&mut args, arg)?;
+                let palette = Palette::parse(spec)?;
+                let palettes = &mut options.palettes;
+                options.palette_index = palettes.iter()
+                    .position(|(name, _)| name.eq_ignore_ascii_case(spec))
+                    .unwrap_or_else(|| {
+                        palettes.push((spec.to_string(), palette));
+                        palettes.len() - 1
+                    });
+            },
+            "--palette-file" => {
+                let custom = palette::load_palette_file(value(&mut args, arg)?)?;
+                if !custom.is_empty() {
+                    options.palette_index = options.palettes.len();
+                }
+                options.palettes.extend(custom);
+            },
+            "--filter" => options.filter = FilterMode::parse(value(&mut args, arg)?)?,
+            "--keymap" => options.keymap.load(value(&mut args, arg)?)?,
+            "--controller" => options.controller_profile.load(value(&mut args, arg)?)?,
+            "--mute" => options.muted = true,
+            "--volume" => {
+                let volume: u32 = number(&mut args, arg)?;
+                if volume > 100 {
+                    return Err(format!("{} must be between 0 and 100", arg));
+                }
+                options.volume = volume as f32 / 100.0;
+            },
+            "--waveform" => options.tone.waveform = Waveform::parse(value(&mut args, arg)?)?,
+            "--frequency" => options.tone.frequency = frequency(&mut args, arg)?,
+            "--low-pass" => options.low_pass = Some(frequency(&mut args, arg)?),
+            "--pause-on-start" => options.pause_on_start = true,
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+
+    for spec in quirks {
+        options.quirks.apply(spec)?;
+    }
+
+    if options.rom_file.is_empty() {
+        return Err("no ROM file given".to_string());
+    }
+    Ok(Command::Run(options))
+}
+
+/// Take the value that follows an option
+fn value<'a>(args: &mut Iter<'a, String>, option: &str) -> Result<&'a str, String> {
+    args.next()
+        .map(|value| value.as_str())
+        .ok_or_else(|| format!("{} needs a value", option))
+}
+
+/// Take the number that follows an option
+fn number<T: FromStr>(args: &mut Iter<String>, option: &str) -> Result<T, String> {
+    let value = value(args, option)?;
+    value.parse().map_err(|_| format!("{} needs a number, not '{}'", option, value))
+}
+
+/// Take the number that follows an option, which can't be zero
+fn positive<T: FromStr + Default + PartialEq>(args: &mut Iter<String>, option: &str) -> Result<T, String> {
+    let value: T = number(args, option)?;
+    if value == T::default() {
+        return Err(format!("{} must be greater than 0", option));
+    }
+    Ok(value)
+}
+
+/// Take the audible frequency in Hz that follows an option
+fn frequency(args: &mut Iter<String>, option: &str) -> Result<f32, String> {
+    let frequency: f32 = number(args, option)?;
+    if !(20.0 ..= 20000.0).contains(&frequency) {
+        return Err(format!("{} must be between 20 and 20000 Hz", option));
+    }
+    Ok(frequency)
+}
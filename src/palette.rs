@@ -0,0 +1,116 @@
+//! Colour palettes for drawing the display.
+//!
+//! A palette has four colours, one for each value of
+//! ``Chip8::get_pixel_color()``: the background, the first XO-CHIP bitplane,
+//! the second bitplane, and pixels that are on in both.
+
+use std::fs::File;
+use std::io::Read;
+
+/// An RGB colour
+pub type Rgb = [u8; 3];
+
+/// The colours used to draw the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub colors: [Rgb; 4],
+}
+
+/// The built-in palettes, in the order they are cycled through
+pub const PRESETS: [(&str, Palette); 6] = [
+    ("classic", Palette { colors: [[0x00, 0x00, 0x00], [0xff, 0xff, 0xff], [0xaa, 0xaa, 0xaa], [0x55, 0x55, 0x55]] }),
+    ("green", Palette { colors: [[0x0a, 0x1a, 0x0a], [0x33, 0xff, 0x66], [0x1a, 0x99, 0x3d], [0xb3, 0xff, 0xc6]] }),
+    ("amber", Palette { colors: [[0x1a, 0x0f, 0x00], [0xff, 0xb0, 0x00], [0x99, 0x66, 0x00], [0xff, 0xdd, 0x88]] }),
+    ("lcd", Palette { colors: [[0x9b, 0xbc, 0x0f], [0x0f, 0x38, 0x0f], [0x8b, 0xac, 0x0f], [0x30, 0x62, 0x30]] }),
+    ("high-contrast", Palette { colors: [[0x00, 0x00, 0x00], [0xff, 0xff, 0x00], [0x00, 0xff, 0xff], [0xff, 0xff, 0xff]] }),
+    ("octo", Palette { colors: [[0x99, 0x66, 0x00], [0xff, 0xcc, 0x00], [0xff, 0x66, 0x00], [0x66, 0x22, 0x00]] }),
+];
+
+impl Default for Palette {
+    /// White on black, like the original frontend
+    fn default() -> Palette {
+        PRESETS[0].1
+    }
+}
+
+impl Palette {
+    /// Get the built-in palette with the given name
+    pub fn preset(name: &str) -> Option<Palette> {
+        PRESETS.iter()
+            .find(|&&(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|&(_, palette)| palette)
+    }
+
+    /// Parse a palette given as the name of a preset, or as 2 or 4 hex
+    /// colours separated by commas or spaces, such as ``#000000,#33ff66``.
+    ///
+    /// With only 2 colours, pixels in either XO-CHIP bitplane are drawn in
+    /// the second one.
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        if let Some(palette) = Palette::preset(spec.trim()) {
+            return Ok(palette);
+        }
+
+        let colors = spec.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|color| !color.is_empty())
+            .map(parse_color)
+            .collect::<Result<Vec<Rgb>, String>>()?;
+
+        match colors.len() {
+            2 => Ok(Palette { colors: [colors[0], colors[1], colors[1], colors[1]] }),
+            4 => Ok(Palette { colors: [colors[0], colors[1], colors[2], colors[3]] }),
+            _ => Err(format!("'{}' is not a palette name, or a list of 2 or 4 colours", spec)),
+        }
+    }
+
+    /// Get the colour of a pixel with the given value, 0 - 3
+    pub fn color(&self, pixel: u8) -> Rgb {
+        self.colors[pixel as usize & 0x3]
+    }
+}
+
+/// Parse a colour written as 6 hex digits, optionally after a ``#``
+fn parse_color(color: &str) -> Result<Rgb, String> {
+    let digits = color.trim_start_matches('#');
+    let value = if digits.len() == 6 { u32::from_str_radix(digits, 16).ok() } else { None };
+    match value {
+        Some(value) => Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8]),
+        None => Err(format!("'{}' is not a colour like #rrggbb", color)),
+    }
+}
+
+/// Parse a palette file.
+///
+/// Each line names a palette and gives its colours as accepted by
+/// ``Palette::parse()``, for example ``sunset = #200020, #ff8040``. Blank
+/// lines and lines starting with ``#`` or ``;`` are ignored.
+pub fn parse_palette_file(source: &str) -> Result<Vec<(String, Palette)>, String> {
+    let mut palettes = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        let mut parts = line.splitn(2, '=');
+        let name = parts.next().unwrap_or("").trim();
+        let spec = match parts.next() {
+            Some(spec) if !name.is_empty() => spec,
+            _ => return Err(format!("line {}: expected '<name> = <colours>'", number + 1)),
+        };
+        let palette = Palette::parse(spec).map_err(|err| format!("line {}: {}", number + 1, err))?;
+        palettes.push((name.to_string(), palette));
+    }
+
+    Ok(palettes)
+}
+
+/// Read and parse a palette file
+pub fn load_palette_file(path: &str) -> Result<Vec<(String, Palette)>, String> {
+    let mut source = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut source))
+        .map_err(|err| format!("{}: {}", path, err))?;
+    parse_palette_file(&source).map_err(|err| format!("{}:{}", path, err))
+}
@@ -0,0 +1,146 @@
+/// Selects how the Chip8 behaves for instructions that were implemented
+/// differently by the various interpreters over the years.
+///
+/// Use one of the presets when you know which platform a ROM was written
+/// for, or tweak individual fields when a ROM needs an unusual mix.
+/// The default is ``Quirks::modern()``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6 and 8xye shift Vy and store the result in Vx.
+    /// When disabled, Vx is shifted in place and Vy is ignored.
+    pub shift_vy: bool,
+
+    /// fx55 and fx65 leave I pointing one past the last register stored
+    /// or loaded. When disabled, I is left unchanged.
+    pub load_store_increment_i: bool,
+
+    /// bnnn jumps to nnn + Vx, where x is the highest nibble of nnn.
+    /// When disabled, it jumps to nnn + V0.
+    pub jump_vx: bool,
+
+    /// 8xy1, 8xy2 and 8xy3 reset Vf to zero.
+    /// When disabled, Vf is left alone.
+    pub vf_reset: bool,
+
+    /// dxyn clips the parts of a sprite that fall off the edges of the
+    /// screen. When disabled, those parts wrap around to the other side.
+    pub clip_sprites: bool,
+
+    /// dxyn waits for the next 60Hz vertical blank before drawing.
+    /// When disabled, sprites are drawn immediately.
+    pub display_wait: bool,
+
+    /// dxy0 draws an 8 x 16 sprite in low resolution mode, as SUPER-CHIP
+    /// 1.1 does. When disabled, it draws 16 x 16 in both resolutions.
+    pub lores_dxy0_8x16: bool,
+}
+
+impl Quirks {
+    /// The names ``preset()`` accepts
+    pub const PRESETS: [&'static str; 5] = ["chip8", "vip", "schip", "xochip", "modern"];
+
+    /// The behaviour of the original interpreter on the COSMAC VIP
+    pub fn vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_increment_i: true,
+            jump_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+            display_wait: true,
+            lores_dxy0_8x16: false,
+        }
+    }
+
+    /// The behaviour of SUPER-CHIP 1.1 on the HP48 calculators
+    pub fn schip() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_increment_i: false,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+            display_wait: false,
+            lores_dxy0_8x16: true,
+        }
+    }
+
+    /// The behaviour of XO-CHIP, as implemented by Octo
+    pub fn xochip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_increment_i: true,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            lores_dxy0_8x16: false,
+        }
+    }
+
+    /// The behaviour most modern interpreters and ROMs written for them
+    /// expect
+    pub fn modern() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_increment_i: false,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+            display_wait: false,
+            lores_dxy0_8x16: false,
+        }
+    }
+
+    /// Get the preset with the given name: ``vip`` (or ``chip8``),
+    /// ``schip``, ``xochip`` or ``modern``
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name.to_ascii_lowercase().as_str() {
+            "vip" | "chip8" => Some(Quirks::vip()),
+            "schip" => Some(Quirks::schip()),
+            "xochip" => Some(Quirks::xochip()),
+            "modern" => Some(Quirks::modern()),
+            _ => None,
+        }
+    }
+
+    /// Change quirks according to a list separated by commas, applied in
+    /// order. Each item is the name of a preset, which replaces every
+    /// quirk, or the name of a field to enable, such as ``shift_vy``.
+    /// Prefix a field with ``no-`` to disable it instead. Dashes and
+    /// underscores are interchangeable in field names.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            if let Some(preset) = Quirks::preset(item) {
+                *self = preset;
+                continue;
+            }
+
+            let name = item.to_ascii_lowercase().replace('-', "_");
+            let (name, enabled) = match name.strip_prefix("no_") {
+                Some(name) => (name.to_string(), false),
+                None => (name, true),
+            };
+            let field = match name.as_str() {
+                "shift_vy" => &mut self.shift_vy,
+                "load_store_increment_i" => &mut self.load_store_increment_i,
+                "jump_vx" => &mut self.jump_vx,
+                "vf_reset" => &mut self.vf_reset,
+                "clip_sprites" => &mut self.clip_sprites,
+                "display_wait" => &mut self.display_wait,
+                "lores_dxy0_8x16" => &mut self.lores_dxy0_8x16,
+                _ => return Err(format!("unknown quirk '{}', expected a preset ({}) or one of shift-vy, \
+                                         load-store-increment-i, jump-vx, vf-reset, clip-sprites, \
+                                         display-wait, lores-dxy0-8x16", item, Quirks::PRESETS.join(", "))),
+            };
+            *field = enabled;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::modern()
+    }
+}
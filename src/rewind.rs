@@ -0,0 +1,196 @@
+//! Keeps a history of snapshots, so a frontend can play the emulation
+//! backwards.
+
+use std::collections::VecDeque;
+
+/// A ring buffer of emulator snapshots that can be played backwards.
+///
+/// Only the newest snapshot is kept in full. Every older one is stored as
+/// the XOR difference to the snapshot after it, run-length encoded. From
+/// one frame to the next very little of the machine changes, so these
+/// differences are almost all zeros and compress to a few bytes, which
+/// makes it cheap to keep many seconds of history.
+pub struct Rewind {
+    /// The maximum number of frames that can be rewound
+    capacity: usize,
+
+    /// The newest snapshot
+    current: Vec<u8>,
+
+    /// Compressed differences between consecutive snapshots, oldest first
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewind {
+    /// Create a buffer that can rewind up to ``capacity`` frames
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            capacity,
+            current: Vec::new(),
+            deltas: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Record the snapshot of a new frame
+    pub fn push(&mut self, state: Vec<u8>) {
+        if self.current.len() == state.len() {
+            let delta: Vec<u8> = self.current.iter().zip(state.iter()).map(|(a, b)| a ^ b).collect();
+            if self.deltas.len() == self.capacity {
+                self.deltas.pop_front();
+            }
+            self.deltas.push_back(compress(&delta));
+        } else {
+            // snapshots of a different size can't be diffed, start over
+            self.deltas.clear();
+        }
+        self.current = state;
+    }
+
+    /// Step back one frame, returning the snapshot of the previous frame,
+    /// or ``None`` if there is no more history.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        decompress_xor(&delta, &mut self.current);
+        Some(&self.current)
+    }
+}
+
+/// Run-length encodes data made up mostly of zeros.
+///
+/// The output is a sequence of chunks, each holding the length of a run of
+/// zeros, the length of the literal bytes that follow it, and those bytes.
+/// Lengths are stored as LEB128 varints.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut index = 0;
+
+    while index < data.len() {
+        let zeros_start = index;
+        while index < data.len() && data[index] == 0 {
+            index += 1;
+        }
+        let literal_start = index;
+        while index < data.len() && data[index] != 0 {
+            index += 1;
+        }
+
+        push_varint(&mut output, literal_start - zeros_start);
+        push_varint(&mut output, index - literal_start);
+        output.extend_from_slice(&data[literal_start .. index]);
+    }
+
+    output
+}
+
+/// Decodes data produced by ``compress()``, XORing it into ``target``
+fn decompress_xor(data: &[u8], target: &mut [u8]) {
+    let mut input = data.iter();
+    let mut index = 0;
+
+    while let Some(zeros) = read_varint(&mut input) {
+        index += zeros;
+        let literals = read_varint(&mut input).unwrap_or(0);
+        for byte in input.by_ref().take(literals) {
+            target[index] ^= *byte;
+            index += 1;
+        }
+    }
+}
+
+fn push_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint<'a, I: Iterator<Item = &'a u8>>(input: &mut I) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *input.next()?;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Compress ``data`` and check that it decompresses back to itself,
+    /// returning the compressed size
+    fn round_trip(data: &[u8]) -> usize {
+        let compressed = compress(data);
+        let mut target = vec![0; data.len()];
+        decompress_xor(&compressed, &mut target);
+        assert_eq!(target, data);
+        compressed.len()
+    }
+
+    #[test]
+    fn zeros_compress_to_a_run_length() {
+        assert_eq!(round_trip(&[]), 0);
+        assert_eq!(round_trip(&[0; 100]), 2);
+        assert_eq!(compress(&[0; 1000]), &[0xe8, 0x07, 0x00]);
+        assert_eq!(round_trip(&[0; 1000]), 3);
+    }
+
+    #[test]
+    fn long_runs_take_several_varint_bytes() {
+        for &len in [127, 128, 129, 300, 16383, 16384].iter() {
+            let mut data = vec![0; len];
+            data.extend(vec![0x5a; len]);
+            data.extend(vec![0; len]);
+            round_trip(&data);
+        }
+        assert_eq!(compress(&[0; 128]), &[0x80, 0x01, 0x00]);
+        assert_eq!(&compress(&[1; 128])[.. 3], &[0x00, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn data_without_zeros_is_kept_as_literals() {
+        let data: Vec<u8> = (1 ..= 255).cycle().take(1000).collect();
+        assert_eq!(round_trip(&data), data.len() + 3);
+    }
+
+    #[test]
+    fn decompressing_xors_into_the_target() {
+        let mut target = vec![0xff, 0x0f, 0x00, 0x10];
+        decompress_xor(&compress(&[0x00, 0x0f, 0x00, 0x11]), &mut target);
+        assert_eq!(target, &[0xff, 0x00, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn pops_frames_back_to_the_oldest_kept() {
+        let mut rewind = Rewind::new(3);
+        assert_eq!(rewind.pop(), None);
+
+        for frame in 0 .. 6u8 {
+            rewind.push(vec![frame, 0, frame * 2, 0xaa]);
+        }
+        for frame in (2 .. 5u8).rev() {
+            assert_eq!(rewind.pop(), Some(&[frame, 0, frame * 2, 0xaa][..]));
+        }
+        assert_eq!(rewind.pop(), None);
+        assert_eq!(rewind.pop(), None);
+
+        // recording carries on from the frame that was rewound to
+        rewind.push(vec![9, 0, 0, 0xaa]);
+        assert_eq!(rewind.pop(), Some(&[2, 0, 4, 0xaa][..]));
+        assert_eq!(rewind.pop(), None);
+    }
+
+    #[test]
+    fn snapshots_of_a_new_size_start_the_history_over() {
+        let mut rewind = Rewind::new(10);
+        rewind.push(vec![1, 2]);
+        rewind.push(vec![3, 4]);
+        rewind.push(vec![5, 6, 7]);
+        assert_eq!(rewind.pop(), None);
+    }
+}
@@ -2,44 +2,188 @@ extern crate sdl2;
 extern crate chip8;
 
 use sdl2::VideoSubsystem;
-use sdl2::video::Window;
-use sdl2::render::{ Canvas };
-use sdl2::pixels::{ Color };
+use sdl2::video::{ FullscreenType, Window, WindowContext };
+use sdl2::render::{ Canvas, Texture, TextureCreator };
+use sdl2::pixels::{ Color, PixelFormatEnum };
+use sdl2::rect::Rect;
 
-use chip8::{ DISPLAY_WIDTH, DISPLAY_HEIGHT };
+use chip8::{ Chip8, DISPLAY_WIDTH, DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT };
+use chip8::filter::{ self, Filter, FilterMode };
+use chip8::frontend::Display;
+use chip8::palette::Palette;
 
-pub struct Screen {
+/// The number of bytes in an RGB24 pixel
+const BYTES_PER_PIXEL: usize = 3;
+
+/// How the emulated display is stretched to fill the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scaling {
+    /// Scale by the largest whole number that fits, so every emulated pixel
+    /// is the same size
+    Integer,
+
+    /// Scale as large as fits while keeping the aspect ratio
+    Aspect,
+}
+
+pub struct Screen<'a> {
     canvas: Canvas<Window>,
+
+    /// Holds the emulated display, large enough for high resolution mode.
+    /// In low resolution mode only the top-left corner is used.
+    texture: Texture<'a>,
+
+    /// The RGB24 pixels uploaded into ``texture``
+    pixels: Vec<u8>,
+
+    /// The value of every pixel of the emulated display when it was last
+    /// drawn, row by row
+    frame: Vec<u8>,
+
+    /// Smooths out flicker between the frames that are presented
+    filter: Filter,
+
+    /// The number of pixels the emulated display has
+    resolution: (u32, u32),
+
+    scaling: Scaling,
+
+    /// The colors pixels are drawn in
+    palette: Palette,
+
+    /// Whether the title says the program is waiting for a key
+    waiting_for_key: bool,
+}
+
+/// Open the window the emulated display is drawn in
+pub fn open_window(video_subsystem: &VideoSubsystem) -> Canvas<Window> {
+    let window = video_subsystem
+        .window("Chip8", (DISPLAY_WIDTH) as u32, (DISPLAY_HEIGHT) as u32)
+        .position_centered()
+        .resizable()
+        .opengl()
+        .build()
+        .unwrap();
+
+    window.into_canvas().build().unwrap()
 }
 
-impl Screen {
-    pub fn new(video_subsystem: &VideoSubsystem) -> Screen {
-        let window = video_subsystem
-            .window("Chip8", (DISPLAY_WIDTH) as u32, (DISPLAY_HEIGHT) as u32)
-            .position_centered()
-            .opengl()
-            .build()
-            .unwrap();
-        
-        let canvas = window.into_canvas().build().unwrap();
-        
+impl<'a> Screen<'a> {
+    /// Draw into ``canvas``, with a texture made by ``texture_creator``,
+    /// which must come from the same canvas
+    pub fn new(canvas: Canvas<Window>, texture_creator: &'a TextureCreator<WindowContext>) -> Screen<'a> {
+        let texture = texture_creator.create_texture_streaming(
+            PixelFormatEnum::RGB24, HIRES_DISPLAY_WIDTH as u32, HIRES_DISPLAY_HEIGHT as u32).unwrap();
+
         Screen {
             canvas,
+            texture,
+            pixels: vec![0; HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT * BYTES_PER_PIXEL],
+            frame: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            filter: Filter::new(FilterMode::None),
+            resolution: (DISPLAY_WIDTH as u32, DISPLAY_HEIGHT as u32),
+            scaling: Scaling::Integer,
+            palette: Palette::default(),
+            waiting_for_key: false,
         }
     }
-    
+
     pub fn set_scale(&mut self, x_scale: u32, y_scale: u32) {
         let window_size = (DISPLAY_WIDTH as u32 * x_scale, DISPLAY_HEIGHT as u32 * y_scale);
-        self.canvas.set_scale(x_scale as f32, y_scale as f32).unwrap();    
         self.canvas.window_mut().set_size(window_size.0, window_size.1).unwrap();
     }
-    
-    pub fn set_pixel(&mut self, color: Color, x: i32, y: i32) {
-        self.canvas.set_draw_color(color);
-        self.canvas.draw_point((x, y)).unwrap();
+
+    /// Switch between filling the whole desktop and a normal window
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        let fullscreen_type = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        self.canvas.window_mut().set_fullscreen(fullscreen_type).unwrap();
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn set_filter(&mut self, mode: FilterMode) {
+        self.filter.set_mode(mode);
     }
-    
-    pub fn update(&mut self) {
-        self.canvas.present()
+
+    pub fn scaling(&self) -> Scaling {
+        self.scaling
+    }
+
+    pub fn set_scaling(&mut self, scaling: Scaling) {
+        self.scaling = scaling;
+    }
+
+    /// Set the number of pixels the emulated display has, stretching them
+    /// to fill the window.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        self.resolution = (width, height);
+    }
+
+    /// Work out where in the window the emulated display goes
+    fn destination(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let (width, height) = self.resolution;
+        let (scaled_width, scaled_height) = match self.scaling {
+            Scaling::Integer => {
+                let scale = (window_width / width).min(window_height / height).max(1);
+                (width * scale, height * scale)
+            },
+            Scaling::Aspect => {
+                let scale = (window_width as f32 / width as f32).min(window_height as f32 / height as f32);
+                ((width as f32 * scale) as u32, (height as f32 * scale) as u32)
+            },
+        };
+
+        // center the display, with black bars around it
+        Rect::new(((window_width as i32) - (scaled_width as i32)) / 2,
+                  ((window_height as i32) - (scaled_height as i32)) / 2,
+                  scaled_width.max(1), scaled_height.max(1))
+    }
+}
+
+impl<'a> Display for Screen<'a> {
+    fn draw(&mut self, chip: &Chip8) {
+        let (width, height) = (chip.display_width(), chip.display_height());
+        self.set_resolution(width as u32, height as u32);
+
+        if chip.is_waiting_for_key() != self.waiting_for_key {
+            self.waiting_for_key = chip.is_waiting_for_key();
+            let title = if self.waiting_for_key { "Chip8 - waiting for a key" } else { "Chip8" };
+            self.canvas.window_mut().set_title(title).unwrap();
+        }
+
+        self.frame.clear();
+        for y in 0 .. height {
+            for x in 0 .. width {
+                self.frame.push(chip.get_pixel_color(x, y));
+            }
+        }
+    }
+
+    fn present(&mut self) {
+        let (width, height) = self.resolution;
+        let destination = self.destination();
+
+        // the filter runs every frame, since faded pixels keep changing
+        // even when the display doesn't
+        let pitch = HIRES_DISPLAY_WIDTH * BYTES_PER_PIXEL;
+        let levels = self.filter.apply(width as usize, height as usize, &self.frame);
+        for (index, level) in levels.iter().enumerate() {
+            let (x, y) = (index % width as usize, index / width as usize);
+            let offset = y * pitch + x * BYTES_PER_PIXEL;
+            self.pixels[offset .. offset + BYTES_PER_PIXEL].copy_from_slice(&filter::mix(&self.palette, *level));
+        }
+
+        // upload the whole framebuffer in one go
+        self.texture.update(None, &self.pixels, pitch).unwrap();
+
+        // the bars around the display are the background color
+        let [r, g, b] = self.palette.color(0);
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, Rect::new(0, 0, width, height), destination).unwrap();
+        self.canvas.present();
     }
 }
@@ -0,0 +1,339 @@
+use std::error::Error;
+use std::fmt;
+
+use super::{ Chip8, KeyWait, Quirks, Rng, MEMORY_SIZE, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT };
+
+/// The first bytes of every snapshot written by ``Chip8::save_state()``
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+
+/// The version of the snapshot format written by ``Chip8::save_state()``.
+/// It must be bumped whenever the layout below changes, and ``load_state()``
+/// taught to read the older layouts.
+pub const STATE_VERSION: u8 = 3;
+
+/// An error raised when a snapshot cannot be restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the snapshot header, so it was not
+    /// written by ``Chip8::save_state()``.
+    BadMagic,
+
+    /// The snapshot was written in a format version this build cannot read.
+    UnsupportedVersion(u8),
+
+    /// The snapshot ends before all of the machine state was read.
+    Truncated,
+
+    /// The snapshot contains a value that no running machine could have,
+    /// such as a stack pointer past the end of the stack.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::BadMagic =>
+                write!(f, "not a Chip8 snapshot"),
+            StateError::UnsupportedVersion(version) =>
+                write!(f, "snapshot version {} is not supported (expected {})", version, STATE_VERSION),
+            StateError::Truncated =>
+                write!(f, "snapshot is truncated"),
+            StateError::Corrupt =>
+                write!(f, "snapshot is corrupt"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+/// Reads the fields of a snapshot in order
+struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        let bytes = self.bytes(8)?;
+        Ok(bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u64))
+    }
+}
+
+impl Chip8 {
+    /// Capture the complete state of the machine.
+    ///
+    /// The snapshot is a versioned binary blob holding memory, registers,
+    /// stack, timers, display, input, quirks and random number generator,
+    /// so restoring it with ``load_state()`` resumes the program exactly
+    /// where it left off. Multi-byte values are stored little endian.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(MEMORY_SIZE + HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT + 256);
+
+        data.extend_from_slice(STATE_MAGIC);
+        data.push(STATE_VERSION);
+
+        data.extend_from_slice(&self.mem);
+        data.extend_from_slice(&self.v);
+        data.push(self.dt);
+        data.push(self.st);
+        data.push(self.sp);
+        for address in self.stack.iter() {
+            data.push(*address as u8);
+            data.push((*address >> 8) as u8);
+        }
+        data.push(self.i as u8);
+        data.push((self.i >> 8) as u8);
+        data.push(self.pc as u8);
+        data.push((self.pc >> 8) as u8);
+        data.extend(self.input.iter().map(|&key| key as u8));
+        data.extend_from_slice(&match self.key_wait {
+            KeyWait::Idle => [0, 0],
+            KeyWait::Waiting => [1, 0],
+            KeyWait::Pressed(key) => [2, key],
+            KeyWait::Released(key) => [3, key],
+        });
+
+        for row in self.display.iter() {
+            data.extend_from_slice(row);
+        }
+        data.push(self.plane);
+        data.extend_from_slice(&self.pattern);
+        data.push(self.pitch);
+        data.push(self.hires as u8);
+        data.extend_from_slice(&self.rpl);
+        data.push(self.exited as u8);
+        data.push(self.render_flag as u8);
+        data.push(self.vblank as u8);
+
+        let quirks = [
+            self.quirks.shift_vy,
+            self.quirks.load_store_increment_i,
+            self.quirks.jump_vx,
+            self.quirks.vf_reset,
+            self.quirks.clip_sprites,
+            self.quirks.display_wait,
+            self.quirks.lores_dxy0_8x16,
+        ];
+        data.extend(quirks.iter().map(|&quirk| quirk as u8));
+
+        let rng = self.rng.state();
+        data.extend((0 .. 8).map(|byte| (rng >> (8 * byte)) as u8));
+
+        data
+    }
+
+    /// Restore the machine from a snapshot taken by ``save_state()``.
+    ///
+    /// Snapshots from earlier versions of the format are still read, and run
+    /// the way the version that wrote them would have. If the snapshot
+    /// cannot be read, the machine is left untouched.
+    ///
+    /// The quirks are part of the snapshot, so they replace the quirks the
+    /// machine was running with.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut reader = StateReader { data };
+
+        if reader.bytes(STATE_MAGIC.len()).map_err(|_| StateError::BadMagic)? != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        // version 1 has no fx0a key wait, and versions 1 and 2 have no
+        // lores_dxy0_8x16 quirk, but are otherwise the same
+        let version = reader.u8()?;
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let mut chip = self.clone();
+
+        chip.mem.copy_from_slice(reader.bytes(MEMORY_SIZE)?);
+        chip.v.copy_from_slice(reader.bytes(0x10)?);
+        chip.dt = reader.u8()?;
+        chip.st = reader.u8()?;
+        chip.sp = reader.u8()?;
+        if chip.sp as usize > chip.stack.len() {
+            return Err(StateError::Corrupt);
+        }
+        for address in chip.stack.iter_mut() {
+            *address = reader.u16()?;
+        }
+        chip.i = reader.u16()?;
+        chip.pc = reader.u16()?;
+        for key in chip.input.iter_mut() {
+            *key = reader.bool()?;
+        }
+        chip.key_wait = if version == 1 {
+            KeyWait::Idle
+        } else {
+            match (reader.u8()?, reader.u8()?) {
+                (0, _) => KeyWait::Idle,
+                (1, _) => KeyWait::Waiting,
+                (2, key) if key < 0x10 => KeyWait::Pressed(key),
+                (3, key) if key < 0x10 => KeyWait::Released(key),
+                _ => return Err(StateError::Corrupt),
+            }
+        };
+
+        for row in chip.display.iter_mut() {
+            row.copy_from_slice(reader.bytes(HIRES_DISPLAY_WIDTH)?);
+            if row.iter().any(|&pixel| pixel > 0x3) {
+                return Err(StateError::Corrupt);
+            }
+        }
+        chip.plane = reader.u8()?;
+        if chip.plane > 0x3 {
+            return Err(StateError::Corrupt);
+        }
+        chip.pattern.copy_from_slice(reader.bytes(0x10)?);
+        chip.pitch = reader.u8()?;
+        chip.hires = reader.bool()?;
+        chip.rpl.copy_from_slice(reader.bytes(0x10)?);
+        chip.exited = reader.bool()?;
+        chip.render_flag = reader.bool()?;
+        chip.vblank = reader.bool()?;
+
+        chip.quirks = Quirks {
+            shift_vy: reader.bool()?,
+            load_store_increment_i: reader.bool()?,
+            jump_vx: reader.bool()?,
+            vf_reset: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            display_wait: reader.bool()?,
+            lores_dxy0_8x16: version >= 3 && reader.bool()?,
+        };
+
+        chip.rng = Rng::from_state(reader.u64()?);
+        if !reader.data.is_empty() {
+            return Err(StateError::Corrupt);
+        }
+
+        *self = chip;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use { KeyWait, Quirks, MEMORY_SIZE, HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT };
+
+    /// Where fields sit in a version 3 snapshot
+    const SP: usize = 5 + MEMORY_SIZE + 0x10 + 2;
+    const KEY_WAIT: usize = SP + 1 + 0x10 * 2 + 4 + 0x10;
+    const DISPLAY: usize = KEY_WAIT + 2;
+    const PLANE: usize = DISPLAY + HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT;
+    const LORES_DXY0_8X16: usize = PLANE + 1 + 0x10 + 1 + 1 + 0x10 + 3 + 6;
+
+    /// A SUPER-CHIP machine that has drawn a digit, called a subroutine and
+    /// is waiting on fx0a with a key held
+    fn busy_chip() -> Chip8 {
+        // ld v0, 3; ld f, v0; drw v0, v0, 5; call 0x20a; ...; ld v1, k
+        let rom = [0x60, 0x03, 0xf0, 0x29, 0xd0, 0x05, 0x22, 0x0a, 0x00, 0x00, 0xf1, 0x0a];
+        let mut chip = Chip8::with_rom_data(&rom, Quirks::schip()).unwrap();
+        chip.seed_rng(7);
+        for _ in 0 .. 5 {
+            chip.tick().unwrap();
+        }
+        chip.key_down(0xb);
+        chip.tick().unwrap();
+        assert_eq!(chip.key_wait, KeyWait::Pressed(0xb));
+        chip
+    }
+
+    #[test]
+    fn round_trips_byte_for_byte() {
+        let chip = busy_chip();
+        let data = chip.save_state();
+        assert_eq!(data[LORES_DXY0_8X16], 1);
+
+        let mut restored = Chip8::with_rom_data(&[], Quirks::modern()).unwrap();
+        restored.load_state(&data).unwrap();
+        assert_eq!(restored.save_state(), data);
+        assert_eq!(restored.get_quirks(), Quirks::schip());
+        assert_eq!(restored.key_wait, KeyWait::Pressed(0xb));
+    }
+
+    #[test]
+    fn loads_older_versions() {
+        let mut data = busy_chip().save_state();
+
+        data[4] = 2;
+        data.remove(LORES_DXY0_8X16);
+        let mut chip = Chip8::with_rom_data(&[], Quirks::schip()).unwrap();
+        chip.load_state(&data).unwrap();
+        assert!(!chip.get_quirks().lores_dxy0_8x16);
+        assert_eq!(chip.key_wait, KeyWait::Pressed(0xb));
+
+        data[4] = 1;
+        data.drain(KEY_WAIT .. KEY_WAIT + 2);
+        let mut chip = Chip8::with_rom_data(&[], Quirks::schip()).unwrap();
+        chip.load_state(&data).unwrap();
+        assert!(!chip.get_quirks().lores_dxy0_8x16);
+        assert_eq!(chip.key_wait, KeyWait::Idle);
+        assert_eq!(chip.get_pc(), 0x20a);
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let data = busy_chip().save_state();
+        let load = |data: &[u8]| Chip8::with_rom_data(&[], Quirks::modern()).unwrap().load_state(data);
+
+        assert_eq!(load(b"C8S"), Err(StateError::BadMagic));
+        assert_eq!(load(b"NOPE"), Err(StateError::BadMagic));
+
+        for &version in [0, STATE_VERSION + 1].iter() {
+            let mut bad = data.clone();
+            bad[4] = version;
+            assert_eq!(load(&bad), Err(StateError::UnsupportedVersion(version)));
+        }
+
+        assert_eq!(load(&data[.. data.len() - 1]), Err(StateError::Truncated));
+        assert_eq!(load(&data[.. SP]), Err(StateError::Truncated));
+
+        let mut long = data.clone();
+        long.push(0);
+        assert_eq!(load(&long), Err(StateError::Corrupt));
+
+        for &(offset, value) in [(SP, 0x11), (KEY_WAIT, 4), (KEY_WAIT + 1, 0x10), (DISPLAY, 4), (PLANE, 4)].iter() {
+            let mut bad = data.clone();
+            bad[offset] = value;
+            assert_eq!(load(&bad), Err(StateError::Corrupt), "offset {}", offset);
+        }
+    }
+
+    #[test]
+    fn failed_loads_leave_the_machine_alone() {
+        let mut chip = busy_chip();
+        let before = chip.save_state();
+
+        let mut bad = Chip8::with_rom_data(&[0x12, 0x00], Quirks::vip()).unwrap().save_state();
+        bad[PLANE] = 4;
+        assert!(chip.load_state(&bad).is_err());
+        assert!(chip.load_state(&bad[.. 100]).is_err());
+        assert_eq!(chip.save_state(), before);
+    }
+}